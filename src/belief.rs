@@ -0,0 +1,231 @@
+// What a player believes the others hold, carried from move to move. It is a
+// particle filter: a set of weighted deals, each played along with the game.
+// A deal that can't make an observed move dies, one that makes a pass it
+// could have played on is down-weighted as in Inference, and when the weight
+// piles up on a few deals they are resampled.
+use rand::Rng;
+
+use {counts, Card, Inference, PartialState, State, Turn};
+
+pub struct Belief {
+    inference: Inference,
+    size: usize,
+    particles: Vec<(State, f64)>,
+    player: u8,
+    // The player's hand and the moves the particles have been played along
+    // with.
+    hand: Vec<Card>,
+    turns: Vec<Turn>,
+}
+
+impl Belief {
+    // `size` deals, started off and weighed with `inference`.
+    pub fn new(size: usize, inference: Inference) -> Belief {
+        assert!(size > 0);
+        Belief {inference: inference, size: size, particles: vec![],
+                player: 0, hand: vec![], turns: vec![]}
+    }
+
+    // Catches up with the moves made since the last update. A new round, or
+    // a different player, starts over. A new round can start with the same
+    // moves as the last one, so it is told apart by the player's own hand:
+    // it has to be the last one less what they have played since.
+    pub fn update<R: Rng>(&mut self, ps: &PartialState, rng: &mut R) {
+        let turns: Vec<Turn> = ps.history().iter()
+            .flat_map(|trick| trick.iter().cloned()).collect();
+        if self.particles.is_empty() || self.player != ps.player()
+                || !turns.starts_with(&self.turns)
+                || !self.holds_on(ps, &turns[self.turns.len()..]) {
+            return self.reset(ps, turns, rng);
+        }
+        for turn in turns[self.turns.len()..].iter() {
+            for particle in self.particles.iter_mut() {
+                let (ref mut state, ref mut weight) = *particle;
+                if *weight == 0. {
+                    continue;
+                }
+                // The player knows why they passed.
+                if turn.muve.is_none() && turn.player != self.player
+                        && state.moves().iter().any(|m| m.is_some()) {
+                    *weight *= self.inference.pass_with_plays;
+                }
+                if state.apply(turn.muve).is_err() {
+                    *weight = 0.;
+                }
+            }
+        }
+        self.hand = ps.hand.clone();
+        self.turns = turns;
+        for particle in self.particles.iter_mut() {
+            if !fits(&particle.0, ps) {
+                particle.1 = 0.;
+            }
+        }
+        if self.effective_size() < self.size as f64 / 2. {
+            self.resample(ps, rng);
+        }
+    }
+
+    // A deal drawn from the belief.
+    pub fn realisation<R: Rng>(&self, rng: &mut R) -> State {
+        let total = self.particles.iter().fold(0., |t, p| t + p.1);
+        let mut pick = rng.gen::<f64>() * total;
+        for &(ref state, weight) in self.particles.iter() {
+            if weight > 0. && pick < weight {
+                return state.clone();
+            }
+            pick -= weight;
+        }
+        // Rounding left a little over.
+        self.particles.iter().rev().find(|p| p.1 > 0.)
+            .expect("no update since the belief died").0.clone()
+    }
+
+    // How many equally weighted deals the particles are worth.
+    pub fn effective_size(&self) -> f64 {
+        let (sum, squares) = self.particles.iter()
+            .fold((0., 0.), |(s, q), p| (s + p.1, q + p.1 * p.1));
+        if squares == 0. { 0. } else { sum * sum / squares }
+    }
+
+    fn reset<R: Rng>(&mut self, ps: &PartialState, turns: Vec<Turn>,
+                     rng: &mut R) {
+        let inference = self.inference;
+        self.particles = (0..self.size).map(|_| {
+            let state = inference.realisation(ps, rng)
+                .ok().expect("inconsistent partial state");
+            (state, 1.)
+        }).collect();
+        self.player = ps.player();
+        self.hand = ps.hand.clone();
+        self.turns = turns;
+    }
+
+    // Whether the player's hand is the one of the last update less what they
+    // played in `turns`.
+    fn holds_on(&self, ps: &PartialState, turns: &[Turn]) -> bool {
+        let mut held = counts(&ps.hand);
+        for turn in turns.iter().filter(|t| t.player == self.player) {
+            if let Some(play) = turn.muve {
+                play.each_rank(|rank, count| held[rank as usize] += count);
+            }
+        }
+        held == counts(&self.hand)
+    }
+
+    // Draws a new set of equally weighted particles, systematically so that
+    // a particle with a large weight is copied about as often as it should.
+    fn resample<R: Rng>(&mut self, ps: &PartialState, rng: &mut R) {
+        let total = self.particles.iter().fold(0., |t, p| t + p.1);
+        if total == 0. {
+            // None of the deals could have played the game.
+            let turns = self.turns.clone();
+            return self.reset(ps, turns, rng);
+        }
+        let step = total / self.size as f64;
+        let mut next = rng.gen::<f64>() * step;
+        let mut sum = 0.;
+        let mut resampled = Vec::with_capacity(self.size);
+        for &(ref state, weight) in self.particles.iter() {
+            sum += weight;
+            while next < sum && resampled.len() < self.size {
+                resampled.push((state.clone(), 1.));
+                next += step;
+            }
+        }
+        self.particles = resampled;
+    }
+}
+
+// Whether `state` looks to the player of `ps` the way the table does.
+fn fits(state: &State, ps: &PartialState) -> bool {
+    *state.hand(ps.player) == ps.hand
+        && ps.hand_sizes.iter().enumerate()
+               .all(|(p, size)| state.hand(p as u8).len() == *size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {counts, Inference, RuleSet, State, DECK};
+    use {THREE, FOUR, FIVE, KING, ACE, TWO};
+    use Play::Set;
+    use tests::{deal, random_game};
+
+    use rand::{Rng, weak_rng};
+    use std::default::Default;
+
+    #[test]
+    fn test_belief_follows_the_game() {
+        let mut belief = Belief::new(20, Inference::default());
+        random_game(RuleSet::default(), 4, &mut weak_rng(), |state, _, rng| {
+            if state.current_player() == 0 {
+                belief.update(&state.to_partial_state(), rng);
+                let real = belief.realisation(rng);
+                assert_eq!(state.hand(0), real.hand(0));
+                assert_eq!(state.moves(), real.moves());
+                for p in 0..4 {
+                    assert_eq!(state.hand(p).len(), real.hand(p).len());
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_belief_resets_between_rounds() {
+        let mut rng = weak_rng();
+        let mut belief = Belief::new(20, Inference::default());
+        for _ in 0..2 {
+            // Nothing has been played yet in either round.
+            let mut deck = DECK.to_vec();
+            rng.shuffle(&mut deck[..]);
+            let state = State::new(4, deck, RuleSet::default());
+            belief.update(&state.to_partial_state(), &mut rng);
+            assert_eq!(state.hand(0), belief.realisation(&mut rng).hand(0));
+        }
+    }
+
+    #[test]
+    fn test_belief_learns_from_passes() {
+        let mut rng = weak_rng();
+        let cards = deal(&[ACE, TWO, FOUR, FIVE, THREE, KING]);
+        let mut state = State::new(3, cards, RuleSet::default());
+        let inference = Inference {pass_with_plays: 0., candidates: 1};
+        let mut belief = Belief::new(100, inference);
+        belief.update(&state.to_partial_state(), &mut rng);
+
+        // Player 1 passes on the KING, so can't have had the TWO, and player
+        // 2 plays the ACE.
+        for m in [Some(Set(1, KING)), None, Some(Set(1, ACE))].iter() {
+            state.apply(*m).unwrap();
+        }
+        belief.update(&state.to_partial_state(), &mut rng);
+        assert_eq!(100., belief.effective_size());
+        for _ in 0..20 {
+            let real = belief.realisation(&mut rng);
+            assert_eq!(counts(state.hand(1)), counts(real.hand(1)));
+        }
+    }
+
+    #[test]
+    fn test_belief_keeps_own_passes_out() {
+        let mut rng = weak_rng();
+        let cards = deal(&[ACE, ACE, FOUR, FIVE, TWO, KING]);
+        let mut state = State::new(3, cards, RuleSet::default());
+        let inference = Inference {pass_with_plays: 0., candidates: 1};
+        let mut belief = Belief::new(100, inference);
+        belief.update(&state.to_partial_state(), &mut rng);
+
+        // Player 1 passes on the KING, so the other ACE is player 2's. Player
+        // 0 passes on the ACE holding the TWO, which says nothing about the
+        // others.
+        for m in [Some(Set(1, KING)), None, Some(Set(1, ACE)), None].iter() {
+            state.apply(*m).unwrap();
+        }
+        belief.update(&state.partial_state_for(0), &mut rng);
+        for _ in 0..20 {
+            let real = belief.realisation(&mut rng);
+            assert_eq!(counts(state.hand(1)), counts(real.hand(1)));
+        }
+    }
+}
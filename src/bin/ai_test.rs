@@ -2,26 +2,64 @@ extern crate env_logger;
 extern crate scum;
 extern crate rand;
 
-use rand::{Rng, weak_rng};
-use scum::{best_move, DECK, State};
+use std::default::Default;
+use std::env;
+use std::fs::File;
+use std::io::Write;
 
+use rand::{Rng, XorShiftRng};
+use scum::{best_move, player_seed, seeded_rng, DECK, GameRecord, RuleSet,
+           State};
+
+// Plays best_move against itself and prints every move. The seed is printed
+// first and giving it again replays the same game. The game is also saved as
+// a record if a file is given:
+//
+//     ai_test [--seed N] [record file]
 fn main() {
     env_logger::init().unwrap();
 
-    let mut rng = weak_rng();
+    let mut seed = None;
+    let mut record_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            seed = Some(args.next().and_then(|s| s.parse().ok())
+                        .expect("--seed needs a number"));
+        } else {
+            record_path = Some(arg);
+        }
+    }
+    let seed = seed.unwrap_or_else(|| rand::random());
+    println!("SEED {}", seed);
+
+    let mut rng = seeded_rng(seed);
     let mut deck = DECK.to_vec();
     rng.shuffle(&mut deck[..]);
-    let mut state = State::new(4, deck);
+    let mut state = State::new(4, deck, RuleSet::default());
     println!("{:?}", state);
+    let mut record = GameRecord::new(
+        vec!["best_move 20x5000".to_string(); 4], Some(seed), &state);
+    let mut rngs: Vec<XorShiftRng> =
+        (0..4).map(|seat| seeded_rng(player_seed(seed, seat))).collect();
     while !state.is_terminal() {
         if state.top_card().is_none() {
             println!("\n");
         }
+        let player = state.current_player() as usize;
         let move_ = best_move(
-            &mut state.to_partial_state(), 20, 5000, &mut rng);
+            &mut state.to_partial_state(), 20, 5000, &mut rngs[player]);
         println!("{} => {:13}  [{:?}]",
             state.current_player(), format!("{:?}", move_), &state);
-        state.apply(move_);
+        state.apply(move_).ok().expect("best_move made an illegal move");
+        record.push(move_);
     }
     println!("\n\nWINNER: {}", state.winner());
+    println!("FINISHING ORDER: {:?}", state.finishing_order());
+    if let Some(path) = record_path {
+        let mut file = File::create(&path)
+            .ok().expect("couldn't create the record file");
+        write!(&mut file, "{}", record).ok()
+            .expect("couldn't write the record");
+    }
 }
@@ -0,0 +1,107 @@
+extern crate scum;
+extern crate rand;
+
+use std::default::Default;
+use std::env;
+
+use rand::Rng;
+use scum::{CheatingUCT, DECK, FairUCT, ISMCTSPlayer, Inference, Player,
+           RuleSet, State, player_seed, round_seed, seeded_rng};
+
+// Realisations a move for FairUCT; the iterations are split between them,
+// so every player gets through the same number of playouts.
+const REALS: usize = 10;
+// Deals the belief matchup keeps track of.
+const PARTICLES: usize = 100;
+
+// The names of the two sides of `matchup`.
+fn names(matchup: &str) -> [&'static str; 2] {
+    match matchup {
+        "dag" => ["tree", "DAG"],
+        "inference" => ["uniform", "inference"],
+        "belief" => ["uniform", "belief"],
+        "ismcts" => ["FairUCT", "ISMCTS"],
+        _ => panic!("unknown matchup {}", matchup),
+    }
+}
+
+fn player(matchup: &str, side: usize, iters: usize) -> Box<Player> {
+    match (matchup, side) {
+        ("dag", 0) => Box::new(CheatingUCT::new(iters)),
+        ("dag", _) => Box::new(CheatingUCT::with_transpositions(iters)),
+        ("inference", 0) => Box::new(FairUCT::new(REALS, iters / REALS)),
+        ("inference", _) => Box::new(FairUCT::with_inference(
+            REALS, iters / REALS, Inference::default())),
+        ("belief", 0) => Box::new(FairUCT::new(REALS, iters / REALS)),
+        ("belief", _) => Box::new(FairUCT::with_belief(
+            REALS, iters / REALS, PARTICLES, Inference::default())),
+        ("ismcts", 0) => Box::new(FairUCT::new(REALS, iters / REALS)),
+        ("ismcts", _) => Box::new(ISMCTSPlayer::new(iters)),
+        _ => panic!("unknown matchup {}", matchup),
+    }
+}
+
+// Plays two versions of an AI against each other at the same number of
+// iterations, and reports how often each wins. The same seed plays the same
+// games again.
+//
+//     compare [--matchup NAME] [games] [iterations] [seed]
+//
+// where the matchup is one of
+//
+//     dag          CheatingUCT as a tree or with its transposition table
+//                  (the default)
+//     inference    FairUCT realising uniformly or with Inference
+//     belief       FairUCT realising uniformly or from a Belief
+//     ismcts       FairUCT against ISMCTSPlayer
+fn main() {
+    let mut matchup = "dag".to_string();
+    let mut args = vec![];
+    let mut all_args = env::args().skip(1);
+    while let Some(arg) = all_args.next() {
+        if arg == "--matchup" {
+            matchup = all_args.next().expect("--matchup needs a name");
+        } else {
+            args.push(arg);
+        }
+    }
+    let matchup = &matchup[..];
+    let games: usize = args.get(0).and_then(|a| a.parse().ok()).unwrap_or(100);
+    let iters: usize = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(1000);
+    if matchup != "dag" && iters < REALS {
+        panic!("FairUCT needs at least {} iterations, one a realisation",
+               REALS);
+    }
+    let seed: u64 = args.get(2).and_then(|a| a.parse().ok())
+        .unwrap_or_else(|| rand::random());
+    println!("{}, seed {}", matchup, seed);
+
+    let names = names(matchup);
+    let mut wins = [0; 2];
+    for game in 0..games {
+        let game_seed = round_seed(seed, game);
+        // The two take every other seat and swap seats every game.
+        let mut players: Vec<Box<Player>> = (0..4)
+            .map(|seat| player(matchup, (seat + game) % 2, iters)).collect();
+        for (seat, player) in players.iter_mut().enumerate() {
+            player.seed(player_seed(game_seed, seat as u8));
+        }
+        let mut deck = DECK.to_vec();
+        seeded_rng(game_seed).shuffle(&mut deck[..]);
+        let mut state = State::new(4, deck, RuleSet::default());
+        while !state.is_terminal() {
+            let player = state.current_player() as usize;
+            let move_ = players[player].choose_move(state.clone());
+            state.apply(move_).ok().expect("an AI made an illegal move");
+        }
+        let side = (state.winner() as usize + game) % 2;
+        wins[side] += 1;
+        println!("game {}: won by #{} ({})",
+                 game + 1, state.winner(), names[side]);
+    }
+    println!("\n{} iterations a move, {} games", iters, games);
+    for (name, won) in names.iter().zip(wins.iter()) {
+        println!("{:>9}: {:>4} wins ({:.1}%)", name, won,
+                 100. * *won as f64 / games as f64);
+    }
+}
@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+
+use {move_string, Move, Play, Rank};
+
+// Why a move or a partial state was rejected. Nothing is changed when a
+// State hands one of these back, so the caller can simply ask again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScumError {
+    // The round is already over.
+    GameOver,
+    // Not a play that can be made here at all, e.g. passing on the lead,
+    // a run without the runs rule or a set on top of a run.
+    IllegalMove(Move),
+    // The play has the wrong number of cards for the top card.
+    IllegalCount(Play),
+    // The play doesn't beat the top card.
+    RankTooLow(Play),
+    // The player doesn't hold enough cards of this rank.
+    CardNotInHand(u8),
+    // A player gave back the wrong number of cards in the exchange. This is
+    // the number that was due.
+    WrongGiftCount(usize),
+    // The partial state doesn't add up, e.g. it holds a card that was never
+    // dealt.
+    InconsistentPartialState(&'static str),
+    // Text that isn't a rank or a move, see the notation module.
+    BadNotation(String),
+}
+
+impl fmt::Display for ScumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScumError::IllegalMove(muve) =>
+                write!(f, "{}: {}", self.description(), move_string(muve)),
+            ScumError::IllegalCount(play) | ScumError::RankTooLow(play) =>
+                write!(f, "{}: {}", self.description(), play),
+            ScumError::CardNotInHand(rank) => match Rank::from_u8(rank) {
+                Some(rank) => write!(f, "{}: {}", self.description(), rank),
+                None => write!(f, "{}: rank {}", self.description(), rank),
+            },
+            ScumError::WrongGiftCount(count) =>
+                write!(f, "{}: {} were due", self.description(), count),
+            ScumError::InconsistentPartialState(why) =>
+                write!(f, "{}: {}", self.description(), why),
+            ScumError::BadNotation(ref text) =>
+                write!(f, "{}: {:?}", self.description(), text),
+            ScumError::GameOver => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for ScumError {
+    fn description(&self) -> &str {
+        match *self {
+            ScumError::GameOver => "the round is over",
+            ScumError::IllegalMove(_) => "illegal move",
+            ScumError::IllegalCount(_) => "wrong number of cards",
+            ScumError::RankTooLow(_) => "doesn't beat the top card",
+            ScumError::CardNotInHand(_) => "card not in hand",
+            ScumError::WrongGiftCount(_) => "wrong number of cards given back",
+            ScumError::InconsistentPartialState(_) =>
+                "inconsistent partial state",
+            ScumError::BadNotation(_) => "can't read that",
+        }
+    }
+}
@@ -0,0 +1,471 @@
+// A one-line text form of positions, like FEN is for chess. The fields are
+// separated by spaces, e.g. three players after the first card:
+//
+//     jcp 8c/5c6c/3c4c 1x7@0 0 - 1,2,0 - 7c -
+//
+//     rules        the RuleSet, see below
+//     hands        everybody's cards separated by `/`, `-` for none
+//     top          the top card and who played it, `-` on the lead
+//     passes       passes since the top card was played
+//     revolution   `R` during a revolution
+//     queue        the players still in the trick, next to play first
+//     finished     the finishing order so far
+//     discard      the cards played, in order
+//     exchanges    cards swapped before play as giver>receiver:cards
+//
+// Cards and moves use the notation of the notation module and lists of
+// players are separated by commas. Empty fields are `-`. Like FEN it is
+// only the position: who played what in earlier tricks is left out.
+//
+// A PartialState only shows the viewer's own hand and writes how many cards
+// the others hold. Two fields follow the exchanges: the viewer, and the
+// cards the viewer hasn't seen, which are somewhere in the other hands.
+//
+// The rules are a letter for every rule that is on, then `:` and the rank of
+// the stop card if there is one:
+//
+//     j  a joker beats any count       e  equal ranks may be played
+//     c  counts must match             k  playing equal skips a player
+//     p  passing locks you out         u  runs
+//     f  the first to finish ends it   w  wild jokers
+//     r  revolutions
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use {Card, CompactState, Exchange, Hand, Move, PartialState, Play, Rank,
+     RuleSet, ScumError, State, MAX_PLAYERS};
+
+static RULE_FLAGS: [char; 9] = ['j', 'c', 'p', 'f', 'r', 'e', 'k', 'u', 'w'];
+
+fn bad(s: &str) -> ScumError {
+    ScumError::BadNotation(s.to_string())
+}
+
+fn fields(s: &str, count: usize) -> Result<Vec<&str>, ScumError> {
+    let fields: Vec<&str> = s.trim().split(' ')
+        .filter(|f| !f.is_empty()).collect();
+    if fields.len() == count { Ok(fields) } else { Err(bad(s)) }
+}
+
+fn rules_string(rules: &RuleSet) -> String {
+    let on = [rules.joker_beats_any_count, rules.counts_must_match,
+              rules.pass_locks_out, rules.end_on_first_finisher,
+              rules.revolution, rules.play_equal, rules.skip_on_equal,
+              rules.runs, rules.wild_jokers];
+    let mut s: String = RULE_FLAGS.iter().zip(on.iter())
+        .filter(|&(_, on)| *on).map(|(flag, _)| *flag).collect();
+    if let Some(rank) = rules.stop_card.and_then(Rank::from_u8) {
+        s.push_str(&format!(":{}", rank));
+    }
+    if s.is_empty() { "-".to_string() } else { s }
+}
+
+fn parse_rules(s: &str) -> Result<RuleSet, ScumError> {
+    let (flags, stop) = match s.find(':') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let flags = if flags == "-" { "" } else { flags };
+    if flags.chars().any(|c| !RULE_FLAGS.contains(&c)) {
+        return Err(bad(s));
+    }
+    let on = |flag: char| flags.chars().any(|c| c == flag);
+    let stop_card = match stop {
+        Some(rank) => Some(try!(rank.parse::<Rank>()) as u8),
+        None => None,
+    };
+    Ok(RuleSet {
+        joker_beats_any_count: on('j'),
+        counts_must_match: on('c'),
+        pass_locks_out: on('p'),
+        end_on_first_finisher: on('f'),
+        revolution: on('r'),
+        play_equal: on('e'),
+        skip_on_equal: on('k'),
+        runs: on('u'),
+        wild_jokers: on('w'),
+        stop_card: stop_card,
+    })
+}
+
+fn cards_string(cards: &[Card]) -> String {
+    if cards.is_empty() {
+        return "-".to_string();
+    }
+    let mut s = String::new();
+    for card in cards.iter() {
+        s.push_str(&format!("{}", card));
+    }
+    s
+}
+
+// Every card ends with its suit, so the suits split the cards up.
+fn parse_cards(s: &str) -> Result<Vec<Card>, ScumError> {
+    let mut cards = vec![];
+    if s == "-" {
+        return Ok(cards);
+    }
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if c == 'c' || c == 'd' || c == 'h' || c == 's' {
+            cards.push(try!(s[start..i + 1].parse()));
+            start = i + 1;
+        }
+    }
+    if start == s.len() { Ok(cards) } else { Err(bad(s)) }
+}
+
+fn players_string(players: &[u8]) -> String {
+    if players.is_empty() {
+        return "-".to_string();
+    }
+    players.iter().map(|p| format!("{}", p)).collect::<Vec<_>>().connect(",")
+}
+
+fn parse_player(s: &str, num_players: usize) -> Result<u8, ScumError> {
+    match s.parse::<u8>() {
+        Ok(player) if (player as usize) < num_players => Ok(player),
+        _ => Err(bad(s)),
+    }
+}
+
+fn parse_players(s: &str, num_players: usize) -> Result<Vec<u8>, ScumError> {
+    if s == "-" {
+        return Ok(vec![]);
+    }
+    let players: Vec<u8> = try!(s.split(',')
+        .map(|p| parse_player(p, num_players)).collect());
+    if players.len() <= num_players { Ok(players) } else { Err(bad(s)) }
+}
+
+fn top_string(top: Move, player: Option<u8>) -> String {
+    match (top, player) {
+        (Some(play), Some(player)) => format!("{}@{}", play, player),
+        (Some(play), None) => format!("{}", play),
+        (None, _) => "-".to_string(),
+    }
+}
+
+fn parse_top(s: &str, num_players: usize)
+             -> Result<(Move, Option<u8>), ScumError> {
+    if s == "-" {
+        return Ok((None, None));
+    }
+    match s.find('@') {
+        Some(i) => {
+            let play = try!(s[..i].parse::<Play>());
+            let player = try!(parse_player(&s[i + 1..], num_players));
+            Ok((Some(play), Some(player)))
+        },
+        None => Ok((Some(try!(s.parse::<Play>())), None)),
+    }
+}
+
+fn parse_revolution(s: &str) -> Result<bool, ScumError> {
+    match s {
+        "R" => Ok(true),
+        "-" => Ok(false),
+        _ => Err(bad(s)),
+    }
+}
+
+fn exchanges_string(exchanges: &[Exchange]) -> String {
+    if exchanges.is_empty() {
+        return "-".to_string();
+    }
+    exchanges.iter()
+        .map(|e| format!("{}>{}:{}", e.giver, e.receiver,
+                         cards_string(&e.cards)))
+        .collect::<Vec<_>>().connect(",")
+}
+
+fn parse_exchanges(s: &str, num_players: usize)
+                   -> Result<Vec<Exchange>, ScumError> {
+    if s == "-" {
+        return Ok(vec![]);
+    }
+    s.split(',').map(|e| {
+        match (e.find('>'), e.find(':')) {
+            (Some(arrow), Some(colon)) if arrow < colon => Ok(Exchange {
+                giver: try!(parse_player(&e[..arrow], num_players)),
+                receiver:
+                    try!(parse_player(&e[arrow + 1..colon], num_players)),
+                cards: try!(parse_cards(&e[colon + 1..])),
+            }),
+            _ => Err(bad(e)),
+        }
+    }).collect()
+}
+
+// Checks the queue and the finishing order against the hand sizes, so that
+// only positions a round can get to are read. Nobody is listed twice, and
+// until the round is over somebody is to play and the finished players are
+// the ones out of cards. At the end only the Scum, or with the `f` rule
+// everybody but the first to finish, may still hold cards.
+fn check_seats(s: &str, rules: &RuleSet, sizes: &[usize], queue: &[u8],
+               finished: &[u8]) -> Result<(), ScumError> {
+    let num_players = sizes.len();
+    let mut seen = vec![false; num_players];
+    for &player in queue.iter().chain(finished.iter()) {
+        if seen[player as usize] {
+            return Err(bad(s));
+        }
+        seen[player as usize] = true;
+    }
+    let terminal = finished.len() == num_players;
+    if terminal != queue.is_empty() {
+        return Err(bad(s));
+    }
+    // Players in the queue aren't finished, so they hold cards too.
+    for player in 0..num_players {
+        let holds = sizes[player] > 0;
+        let ok = match finished.iter().position(|&p| p as usize == player) {
+            None => holds,
+            Some(i) => !holds || terminal && (i + 1 == num_players
+                || rules.end_on_first_finisher && i > 0),
+        };
+        if !ok {
+            return Err(bad(s));
+        }
+    }
+    Ok(())
+}
+
+// Seats the players of a parsed position.
+fn seat(core: &mut CompactState, top: (Move, Option<u8>), passes: &str,
+        revolution: &str, queue: Vec<u8>, finished: &[u8])
+        -> Result<(), ScumError> {
+    core.top_card = top.0;
+    core.top_player = top.1;
+    core.passes = try!(passes.parse().map_err(|_| bad(passes)));
+    core.revolution = try!(parse_revolution(revolution));
+    core.next_player.clear();
+    for player in queue.into_iter() {
+        core.next_player.push_back(player);
+    }
+    for &player in finished.iter() {
+        core.finish(player);
+    }
+    core.rehash();
+    Ok(())
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hands: Vec<String> =
+            self.hands.iter().map(|h| cards_string(h)).collect();
+        write!(f, "{} {} {} {} {} {} {} {} {}",
+               rules_string(self.rules()), hands.connect("/"),
+               top_string(self.core.top_card, self.core.top_player),
+               self.core.passes, if self.revolution() { "R" } else { "-" },
+               players_string(&self.core.next_player.to_vec()),
+               players_string(self.finishing_order()),
+               cards_string(&self.discard),
+               exchanges_string(&self.exchanges))
+    }
+}
+
+impl FromStr for State {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<State, ScumError> {
+        let f = try!(fields(s, 9));
+        let rules = try!(parse_rules(f[0]));
+        let mut hands: Vec<Hand> =
+            try!(f[1].split('/').map(parse_cards).collect());
+        if hands.len() > MAX_PLAYERS {
+            return Err(bad(f[1]));
+        }
+        for hand in hands.iter_mut() {
+            hand.sort();
+        }
+        let top = try!(parse_top(f[2], hands.len()));
+        let queue = try!(parse_players(f[5], hands.len()));
+        let finished = try!(parse_players(f[6], hands.len()));
+        let sizes: Vec<usize> = hands.iter().map(|h| h.len()).collect();
+        try!(check_seats(s, &rules, &sizes, &queue, &finished));
+        let discard = try!(parse_cards(f[7]));
+        let exchanges = try!(parse_exchanges(f[8], hands.len()));
+        let mut core = CompactState::new(rules, &hands, &discard);
+        try!(seat(&mut core, top, f[3], f[4], queue, &finished));
+        let mut cards: Vec<Card> = hands.iter().flat_map(|h| h.iter())
+            .chain(discard.iter()).cloned().collect();
+        cards.sort();
+        Ok(State {core: core, hands: hands, cards: cards, discard: discard,
+                  exchanges: exchanges, history: vec![]})
+    }
+}
+
+// The cards that are neither in the viewer's hand nor played.
+fn unseen(p: &PartialState) -> Vec<Card> {
+    let mut unseen = p.cards.clone();
+    for card in p.hand.iter().chain(p.discard.iter()) {
+        if let Some(i) = unseen.iter().position(|c| c == card) {
+            unseen.remove(i);
+        }
+    }
+    unseen
+}
+
+impl fmt::Display for PartialState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hands: Vec<String> = self.hand_sizes.iter().enumerate()
+            .map(|(i, size)| if i == self.player as usize {
+                cards_string(&self.hand)
+            } else if *size == 0 {
+                "-".to_string()
+            } else {
+                format!("{}", size)
+            }).collect();
+        let mut exchanges = self.gave.clone();
+        exchanges.extend(self.received.iter().cloned());
+        write!(f, "{} {} {} {} {} {} {} {} {} {} {}",
+               rules_string(&self.rules), hands.connect("/"),
+               top_string(self.top_card, self.top_player), self.passes,
+               if self.revolution { "R" } else { "-" },
+               players_string(&self.next_player.iter().cloned()
+                   .collect::<Vec<_>>()),
+               players_string(&self.finishing_order),
+               cards_string(&self.discard), exchanges_string(&exchanges),
+               self.player, cards_string(&unseen(self)))
+    }
+}
+
+impl FromStr for PartialState {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<PartialState, ScumError> {
+        let f = try!(fields(s, 11));
+        let rules = try!(parse_rules(f[0]));
+        let hand_fields: Vec<&str> = f[1].split('/').collect();
+        let num_players = hand_fields.len();
+        let player = try!(parse_player(f[9], num_players));
+        let mut hand = try!(parse_cards(hand_fields[player as usize]));
+        hand.sort();
+        let hand_sizes: Vec<usize> = try!(hand_fields.iter().enumerate()
+            .map(|(i, h)| if i == player as usize {
+                Ok(hand.len())
+            } else if *h == "-" {
+                Ok(0)
+            } else {
+                h.parse().map_err(|_| bad(h))
+            }).collect());
+        let (top_card, top_player) = try!(parse_top(f[2], num_players));
+        let queue = try!(parse_players(f[5], num_players));
+        let finished = try!(parse_players(f[6], num_players));
+        try!(check_seats(s, &rules, &hand_sizes, &queue, &finished));
+        let discard = try!(parse_cards(f[7]));
+        let exchanges = try!(parse_exchanges(f[8], num_players));
+        if exchanges.iter().any(|e| e.giver != player && e.receiver != player) {
+            return Err(bad(f[8]));
+        }
+        let mut cards = try!(parse_cards(f[10]));
+        cards.extend(hand.iter().chain(discard.iter()).cloned());
+        cards.sort();
+        Ok(PartialState {
+            rules: rules,
+            player: player,
+            hand_sizes: hand_sizes,
+            hand: hand,
+            cards: cards,
+            discard: discard,
+            next_player: queue.into_iter().collect::<VecDeque<u8>>(),
+            top_card: top_card,
+            top_player: top_player,
+            passes: try!(f[3].parse().map_err(|_| bad(f[3]))),
+            revolution: try!(parse_revolution(f[4])),
+            finishing_order: finished,
+            gave: exchanges.iter()
+                .filter(|e| e.giver == player).cloned().collect(),
+            received: exchanges.iter()
+                .filter(|e| e.receiver == player).cloned().collect(),
+            history: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {DECK, PartialState, RuleSet, State};
+    use {THREE, FOUR, FIVE, SIX, SEVEN, EIGHT};
+    use tests::{deal, random_game};
+
+    use rand::{Rng, weak_rng};
+    use std::default::Default;
+
+    #[test]
+    fn test_state_text() {
+        let mut state = State::new(3, deal(&[
+            THREE, FOUR, FIVE, SIX, SEVEN, EIGHT]), RuleSet::default());
+        assert_eq!("jcp 7c8c/5c6c/3c4c - 0 - 0,1,2 - - -", state.to_string());
+        let first = state.moves()[0];
+        state.apply(first).unwrap();
+        let text = "jcp 8c/5c6c/3c4c 1x7@0 0 - 1,2,0 - 7c -";
+        assert_eq!(text, state.to_string());
+        let parsed: State = text.parse().unwrap();
+        assert_eq!(format!("{:?}", state.compact()),
+                   format!("{:?}", parsed.compact()));
+        assert!(parsed.history().is_empty());
+
+        assert_eq!("jcp 1/5c6c/2 1x7@0 0 - 1,2,0 - 7c - 1 3c4c8c",
+                   state.partial_state_for(1).to_string());
+    }
+
+    // Both texts of `state` read back as the same position.
+    fn round_trip<R: Rng>(state: &State, rng: &mut R) {
+        let text = state.to_string();
+        let parsed: State = text.parse().unwrap();
+        assert_eq!(text, parsed.to_string());
+        assert_eq!(format!("{:?}", state.compact()),
+                   format!("{:?}", parsed.compact()));
+        assert_eq!(state.cards(), parsed.cards());
+        assert_eq!(state.zobrist(), parsed.zobrist());
+
+        let partial = state.partial_state_for(0);
+        let text = partial.to_string();
+        let parsed: PartialState = text.parse().unwrap();
+        assert_eq!(text, parsed.to_string());
+        assert_eq!(partial.zobrist(), parsed.zobrist());
+        State::realisation_from(&parsed, rng).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = weak_rng();
+        let rules = RuleSet {stop_card: Some(EIGHT), revolution: true,
+                             pass_locks_out: false, .. RuleSet::default()};
+        let mut state = State::new(4, DECK.to_vec(), rules);
+        let gift = state.hand(2)[..2].to_vec();
+        state.exchange(2, 0, gift).unwrap();
+        round_trip(&state, &mut rng);
+
+        let state = random_game(rules, 4, &mut rng, |state, _, rng| {
+            round_trip(state, rng);
+        });
+        let parsed: State = state.to_string().parse().unwrap();
+        assert!(parsed.is_terminal());
+    }
+
+    #[test]
+    fn test_bad_text() {
+        for bad in ["", "jcp 7c8c", "jcx 8c/5c6c/3c4c - 0 - 0,1,2 - - -",
+                    "jcp 8c/5c6x/3c4c - 0 - 0,1,2 - - -",
+                    "jcp 8c/5c6c/3c4c - 0 - 0,1,3 - - -",
+                    "jcp 8c/5c6c/3c4c 1x7@5 0 - 0,1,2 - - -",
+                    // Nobody to play before the end.
+                    "jcp 7c/5c - 0 - - - - -",
+                    // Listed twice.
+                    "jcp 8c/5c6c/3c4c - 0 - 0,1,1 - - -",
+                    "jcp 8c/5c6c/- - 0 - 0,1 2,2 - - -",
+                    "jcp 8c/5c6c/- - 0 - 0,1,2 2 - - -",
+                    // Out of cards but still playing, or not finished.
+                    "jcp 8c/5c6c/- - 0 - 0,1,2 - - -",
+                    "jcp 8c/5c6c/- - 0 - 0,1 - - - -",
+                    // Finished with cards left.
+                    "jcp 8c/5c6c/3c - 0 - 0,1 2 - - -",
+                    "jcp 8c/5c6c/3c - 0 - - 2,0,1 - - -"].iter() {
+            assert!(bad.parse::<State>().is_err(), "parsed {:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Realisations that take the table's moves into account. A uniform deal of
+// the unseen cards ignores what everybody did: somebody who passed on a single
+// FOUR probably had nothing to beat it with. Here several deals are drawn and
+// one is kept with odds in proportion to how well it explains the history.
+use rand::Rng;
+use std::default::Default;
+
+use {counts, run_moves, set_moves, Counts, Move, PartialState, Play,
+     RuleSet, ScumError, State, JOKER};
+use Play::{Run, Set, Wild};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Inference {
+    // How likely a player is to pass when they could have played. At 0 deals
+    // that contradict a pass are thrown out.
+    pub pass_with_plays: f64,
+    // How many uniform deals to draw for every one used.
+    pub candidates: usize,
+}
+
+impl Default for Inference {
+    fn default() -> Inference {
+        Inference {pass_with_plays: 0.2, candidates: 20}
+    }
+}
+
+impl Inference {
+    // A realisation of `ps`, favouring deals that explain the history.
+    pub fn realisation<R: Rng>(&self, ps: &PartialState, rng: &mut R)
+                               -> Result<State, ScumError> {
+        let mut deals = Vec::with_capacity(self.candidates);
+        let mut weights = Vec::with_capacity(self.candidates);
+        for _ in 0..self.candidates {
+            let state = try!(State::realisation_from(ps, rng));
+            weights.push(self.likelihood(ps, &state));
+            deals.push(state);
+        }
+        let total = weights.iter().fold(0., |t, w| t + w);
+        if total == 0. {
+            // Nothing fits; any deal is as good as another.
+            return State::realisation_from(ps, rng);
+        }
+        let mut pick = rng.gen::<f64>() * total;
+        // Rounding can leave a little over, so the last deal that fits
+        // stands in for running off the end.
+        let mut chosen = 0;
+        for (i, weight) in weights.iter().enumerate() {
+            if *weight > 0. {
+                chosen = i;
+                if pick < *weight {
+                    break;
+                }
+            }
+            pick -= *weight;
+        }
+        Ok(deals.swap_remove(chosen))
+    }
+
+    // How likely the other players' moves so far are if they were dealt the
+    // hands of `state`, up to a constant.
+    pub fn likelihood(&self, ps: &PartialState, state: &State) -> f64 {
+        let rules = state.rules();
+        // Walk back through the history, giving the players back the cards
+        // they played, so their hands are as they were at every move.
+        let mut hands: Vec<Counts> = (0..state.num_players())
+            .map(|p| counts(state.hand(p as u8))).collect();
+        let mut revolution = state.revolution();
+        let mut likelihood = 1.;
+        for trick in ps.history().iter().rev() {
+            for (i, turn) in trick.iter().enumerate().rev() {
+                let hand = &mut hands[turn.player as usize];
+                if let Some(play) = turn.muve {
+                    play.each_rank(|rank, count| hand[rank as usize] += count);
+                    if toggles_revolution(play.effective(), rules) {
+                        revolution = !revolution;
+                    }
+                }
+                if turn.player == ps.player() || turn.muve.is_some() {
+                    continue;
+                }
+                let top = trick[..i].iter().rev()
+                    .filter_map(|t| t.muve).next();
+                if could_play(hand, top, rules, revolution) {
+                    likelihood *= self.pass_with_plays;
+                }
+            }
+        }
+        likelihood
+    }
+}
+
+fn toggles_revolution(play: Play, rules: &RuleSet) -> bool {
+    match play {
+        Set(count, rank) => rules.revolution && count >= 4 && rank != JOKER,
+        _ => false,
+    }
+}
+
+// Whether `hand` had anything to play on `top`.
+fn could_play(hand: &Counts, top: Move, rules: &RuleSet, revolution: bool)
+              -> bool {
+    let mut moves = vec![];
+    match top.map(|top| top.effective()) {
+        // Nobody passes on the lead.
+        None => return true,
+        Some(Set(count, card)) =>
+            set_moves(hand, count, card, rules, revolution, &mut moves),
+        Some(Run(length, low)) =>
+            run_moves(hand, length, low, rules, revolution, &mut moves),
+        Some(Wild(..)) => unreachable!(),
+    }
+    moves.iter().any(|m| m.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {RuleSet, State};
+    use {THREE, FOUR, FIVE, KING, ACE, TWO};
+    use Play::Set;
+    use tests::deal;
+
+    use rand::weak_rng;
+    use std::default::Default;
+
+    // Player 0 leads a KING, player 1 passes and player 2 plays an ACE. Only
+    // player 2 can be holding the TWO.
+    fn after_pass() -> State {
+        let cards = deal(&[ACE, TWO, FOUR, FIVE, THREE, KING]);
+        let mut state = State::new(3, cards, RuleSet::default());
+        for m in [Some(Set(1, KING)), None, Some(Set(1, ACE))].iter() {
+            state.apply(*m).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn test_likelihood() {
+        let state = after_pass();
+        let ps = state.to_partial_state();
+        let inference = Inference::default();
+        assert_eq!(1., inference.likelihood(&ps, &state));
+
+        let mut rng = weak_rng();
+        let mut seen_unlikely = false;
+        for _ in 0..100 {
+            let deal = State::realisation_from(&ps, &mut rng).unwrap();
+            let holds_two = deal.hand(1).iter().any(|c| c.rank() == TWO);
+            let expected = if holds_two { 0.2 } else { 1. };
+            assert_eq!(expected, inference.likelihood(&ps, &deal));
+            seen_unlikely |= holds_two;
+        }
+        assert!(seen_unlikely);
+    }
+
+    #[test]
+    fn test_rejection() {
+        let state = after_pass();
+        let ps = state.to_partial_state();
+        let inference = Inference {pass_with_plays: 0., candidates: 100};
+        let mut rng = weak_rng();
+        for _ in 0..20 {
+            let deal = inference.realisation(&ps, &mut rng).unwrap();
+            assert_eq!(state.hand(1), deal.hand(1));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Single-observer Information Set MCTS. best_move searches every realisation
+// with a tree of its own and adds up the visits, which lets each tree act as
+// if it knew the hidden hands ("strategy fusion"). Here there is one tree over
+// the moves everybody sees, and every iteration deals the unseen cards again.
+// A move only exists in some of the deals, so a child is scored against how
+// often it was there to be picked rather than how often its parent was.
+use rand::{Rng, XorShiftRng, weak_rng};
+use std::num::Float;
+
+use {rollout, seeded_rng, CompactState, FairPlayer, Move, PartialState, State,
+     UCTK};
+
+pub struct ISMCTSPlayer {
+    rng: XorShiftRng,
+    iters: usize,
+}
+
+impl ISMCTSPlayer {
+    pub fn new(iters: usize) -> ISMCTSPlayer {
+        ISMCTSPlayer {rng: weak_rng(), iters: iters}
+    }
+}
+
+impl FairPlayer for ISMCTSPlayer {
+    fn choose_move(&mut self, p: PartialState) -> Move {
+        search(&p, self.iters, &mut self.rng)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed);
+    }
+}
+
+// The move chosen after `iters` iterations from the information set `p`.
+pub fn search<R: Rng>(p: &PartialState, iters: usize, rng: &mut R) -> Move {
+    let mut root = Node::new(None, p.player());
+    let mut scratch = None;
+    for i in 0..iters {
+        let mut state = State::realisation_from(p, rng)
+            .ok().expect("inconsistent partial state").compact();
+        if i == 0 {
+            let moves = state.moves();
+            if moves.len() == 1 {
+                return moves[0];
+            }
+            scratch = Some(state);
+        }
+        root.iterate(&mut state, scratch.as_mut().unwrap(), rng);
+    }
+    root.children.iter().max_by(|c| c.plays as usize)
+        .expect("no iterations were run").muve
+}
+
+struct Node {
+    muve: Move,
+    // Who made `muve`, and so whose wins count here.
+    player: u8,
+    children: Vec<Node>,
+    plays: f64,
+    wins: f64,
+    // How many times the node could have been picked.
+    available: f64,
+}
+
+impl Node {
+    fn new(muve: Move, player: u8) -> Node {
+        Node {muve: muve, player: player, children: vec![], plays: 0.,
+              wins: 0., available: 0.}
+    }
+
+    // One iteration down from here in the deal `state`, which is the same
+    // again afterwards, with the playout in `scratch`. Returns the winner.
+    fn iterate<R: Rng>(&mut self, state: &mut CompactState,
+                       scratch: &mut CompactState, rng: &mut R) -> u8 {
+        if state.is_terminal() {
+            return state.winner();
+        }
+        let player = state.current_player();
+        let moves = state.moves();
+        for child in self.children.iter_mut() {
+            if moves.contains(&child.muve) {
+                child.available += 1.;
+            }
+        }
+        let untried: Vec<Move> = moves.iter().cloned()
+            .filter(|m| self.children.iter().all(|c| c.muve != *m))
+            .collect();
+        if !untried.is_empty() {
+            let muve = *rng.choose(&untried[..]).unwrap();
+            let undo = state.apply(muve)
+                .ok().expect("generated an illegal move");
+            let winner = rollout(state, scratch, rng);
+            state.undo(undo);
+            let mut child = Node::new(muve, player);
+            child.available = 1.;
+            child.update(winner);
+            self.children.push(child);
+            return winner;
+        }
+        let i = self.select(&moves);
+        let undo = state.apply(self.children[i].muve)
+            .ok().expect("generated an illegal move");
+        let winner = self.children[i].iterate(state, scratch, rng);
+        state.undo(undo);
+        self.children[i].update(winner);
+        winner
+    }
+
+    // The best child among those whose move is legal in this deal.
+    fn select(&self, moves: &[Move]) -> usize {
+        (0..self.children.len())
+            .filter(|i| moves.contains(&self.children[*i].muve))
+            .max_by(|i| {
+                let c = &self.children[*i];
+                ((c.wins / c.plays
+                  + UCTK * (c.available.ln() / c.plays).sqrt()) * 1000000.)
+                    as i64
+            }).unwrap()
+    }
+
+    fn update(&mut self, winner: u8) {
+        self.plays += 1.;
+        if winner == self.player {
+            self.wins += 1.;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {FairPlayer, RuleSet, State};
+    use {THREE, FOUR, FIVE, SIX};
+    use Play::Set;
+    use tests::{deal, random_game};
+
+    use rand::weak_rng;
+    use std::default::Default;
+
+    #[test]
+    fn test_plays_the_pair_out() {
+        // Leading the pair of THREEs wins on the spot; a single THREE gets
+        // beaten and player 1 goes out first.
+        let cards = deal(&[FOUR, FIVE, SIX, THREE, THREE]);
+        let state = State::new(2, cards, RuleSet::default());
+        let muve = search(&state.to_partial_state(), 500, &mut weak_rng());
+        assert_eq!(Some(Set(2, THREE)), muve);
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        let mut player = ISMCTSPlayer::new(100);
+        random_game(RuleSet::default(), 4, &mut weak_rng(), |state, _, _| {
+            let muve = player.choose_move(state.to_partial_state());
+            assert!(state.check_move(muve).is_ok());
+        });
+    }
+}
@@ -10,35 +10,254 @@ extern crate rand;
 #[macro_use]
 extern crate log;
 
+use Play::{Run, Set, Wild};
+
+pub use belief::Belief;
+pub use error::ScumError;
+pub use inference::Inference;
+pub use ismcts::ISMCTSPlayer;
+pub use matches::Match;
+pub use notation::{move_string, parse_move, trick_string};
+pub use record::GameRecord;
+pub use scoring::{MatchEnd, Scoring, Standings};
+pub use seed::{player_seed, round_seed, seeded_rng};
+pub use trace::import_trace;
+pub use transposition::TranspositionSearch;
+
+mod belief;
+mod error;
+mod fen;
+mod inference;
+mod ismcts;
+mod matches;
+mod notation;
+mod record;
+mod scoring;
+mod seed;
+mod trace;
+mod transposition;
+mod zobrist;
+
 use rand::{Rng, XorShiftRng};
-use std::collections::{HashMap, VecDeque};
-use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::fmt::Write;
+use std::cmp::max;
+use std::default::Default;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
 use std::num::Float;
 use std::str::FromStr;
 
+// A round with every card accounted for. Whose turn it is and what is on
+// top lives in `core`; the hands here say which suits everybody holds.
 #[derive(Clone, Debug)]
 pub struct State {
+    core: CompactState,
     hands: Vec<Hand>,
-    top_card: Move,
-    discard: Vec<u8>,
-    next_player: VecDeque<u8>,
-    finishing_order: Vec<u8>,
+    cards: Vec<Card>,
+    discard: Vec<Card>,
+    exchanges: Vec<Exchange>,
+    history: Vec<Vec<Turn>>,
 }
 
 pub struct PartialState {
+    rules: RuleSet,
     player: u8,
     hand_sizes: Vec<usize>,
     hand: Hand,
-    discard: Vec<u8>,
+    cards: Vec<Card>,
+    discard: Vec<Card>,
     next_player: VecDeque<u8>,
     top_card: Move,
+    top_player: Option<u8>,
+    passes: usize,
+    revolution: bool,
     finishing_order: Vec<u8>,
+    gave: Vec<Exchange>,
+    received: Vec<Exchange>,
+    history: Vec<Vec<Turn>>,
+}
+
+// The house rules a game is played with. The default is the classic game,
+// played until everybody has gone out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RuleSet {
+    // A single joker beats a set of any size. Otherwise jokers are just the
+    // highest rank and can be played in pairs.
+    pub joker_beats_any_count: bool,
+    // A play must have as many cards as the top card. Otherwise playing more
+    // cards of a higher rank is fine too.
+    pub counts_must_match: bool,
+    // Passing takes you out of the trick. Otherwise the trick goes around
+    // until everybody passes in a row.
+    pub pass_locks_out: bool,
+    // The round ends as soon as somebody goes out, and everybody else is
+    // ranked by the number of cards they still hold.
+    pub end_on_first_finisher: bool,
+    // Playing four of a kind flips the order of THREE to TWO for the rest of
+    // the round, or until somebody plays four of a kind again.
+    pub revolution: bool,
+    // Playing this rank (e.g. EIGHT) ends the trick straight away.
+    pub stop_card: Option<u8>,
+    // A set may be played on a set of the same rank.
+    pub play_equal: bool,
+    // Playing the same rank as the top card skips the next player.
+    pub skip_on_equal: bool,
+    // Runs of at least MIN_RUN consecutive ranks may be played. A run can
+    // only be beaten by a higher run of the same length (or a joker).
+    pub runs: bool,
+    // Jokers can stand in for any rank to complete a set, e.g. two NINEs and
+    // a joker make three NINEs.
+    pub wild_jokers: bool,
+}
+
+impl Default for RuleSet {
+    fn default() -> RuleSet {
+        RuleSet {
+            joker_beats_any_count: true,
+            counts_must_match: true,
+            pass_locks_out: true,
+            end_on_first_finisher: false,
+            revolution: false,
+            stop_card: None,
+            play_equal: false,
+            skip_on_equal: false,
+            runs: false,
+            wild_jokers: false,
+        }
+    }
+}
+
+// Cards passed between the President and the Scum (and so on) before a round.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exchange {
+    pub giver: u8,
+    pub receiver: u8,
+    pub cards: Vec<Card>,
+}
+
+// A move as the table saw it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Turn {
+    pub player: u8,
+    pub muve: Move,
+}
+
+impl PartialState {
+    pub fn player(&self) -> u8 { self.player }
+    pub fn revolution(&self) -> bool { self.revolution }
+    pub fn hand<'a>(&'a self) -> &'a Hand { &self.hand }
+    pub fn gave<'a>(&'a self) -> &'a [Exchange] { &self.gave[..] }
+    pub fn received<'a>(&'a self) -> &'a [Exchange] { &self.received[..] }
+    // Every move so far, trick by trick.
+    pub fn history<'a>(&'a self) -> &'a [Vec<Turn>] { &self.history[..] }
+
+    // Who played each card on the discard pile. All None when the history
+    // doesn't account for the whole pile, as for a position read from text.
+    fn discard_players(&self) -> Vec<Option<u8>> {
+        let mut players = vec![];
+        for turn in self.history.iter().flat_map(|t| t.iter()) {
+            if let Some(play) = turn.muve {
+                play.each_rank(|_, count| {
+                    for _ in 0..count {
+                        players.push(Some(turn.player));
+                    }
+                });
+            }
+        }
+        if players.len() != self.discard.len() {
+            return vec![None; self.discard.len()];
+        }
+        players
+    }
+
+    // A hash of the information set: only what the player can see, so all
+    // the states they can't tell apart hash the same.
+    pub fn zobrist(&self) -> u64 {
+        let mut hash = zobrist::viewer(self.player)
+            ^ zobrist::top_card(self.top_card)
+            ^ zobrist::top_player(self.top_player)
+            ^ zobrist::passes(self.passes as u8)
+            ^ zobrist::revolution(self.revolution);
+        let hand = counts(&self.hand);
+        let discard = counts(&self.discard);
+        for rank in THREE..JOKER + 1 {
+            hash ^= zobrist::hand(self.player, rank, hand[rank as usize])
+                ^ zobrist::discard(rank, discard[rank as usize]);
+        }
+        for (i, size) in self.hand_sizes.iter().enumerate() {
+            if i != self.player as usize {
+                hash ^= zobrist::hand_size(i as u8, *size);
+            }
+        }
+        hash ^= zobrist::queue(self.next_player.iter().cloned());
+        for (i, player) in self.finishing_order.iter().enumerate() {
+            hash ^= zobrist::finished(i, *player);
+        }
+        for e in self.gave.iter().chain(self.received.iter()) {
+            let cards = counts(&e.cards);
+            for rank in THREE..JOKER + 1 {
+                hash ^= zobrist::gift(
+                    e.giver, e.receiver, rank, cards[rank as usize]);
+            }
+        }
+        hash
+    }
+}
+
+// None is a pass.
+pub type Move = Option<Play>;
+pub type Hand = Vec<Card>;
+// How many cards of each rank a hand holds, indexed by rank.
+pub type Counts = [u8; 15];
+
+pub fn counts(hand: &[Card]) -> Counts {
+    let mut counts = [0; 15];
+    for card in hand.iter() {
+        counts[card.rank() as usize] += 1;
+    }
+    counts
+}
+
+// The most players a table can seat.
+pub const MAX_PLAYERS: usize = 64;
+
+// The cards played are always the ones of each rank with the lowest suits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Play {
+    // Number of cards and their rank.
+    Set(u8, u8),
+    // Length and lowest rank of a run of single cards of consecutive ranks.
+    Run(u8, u8),
+    // A set of `count` cards of `rank`, the last `jokers` of which are jokers.
+    Wild(u8, u8, u8),
+}
+
+impl Play {
+    // What the play counts as: a wild set is as good as a set of its rank.
+    pub fn effective(self) -> Play {
+        match self {
+            Wild(count, rank, _) => Set(count, rank),
+            play => play,
+        }
+    }
+
+    // Calls `f(rank, count)` for the cards the play takes from a hand, the
+    // jokers of a wild set last.
+    fn each_rank<F: FnMut(u8, u8)>(self, mut f: F) {
+        match self {
+            Set(count, rank) => f(rank, count),
+            Run(length, low) => for rank in low..low + length {
+                f(rank, 1);
+            },
+            Wild(count, rank, jokers) => {
+                f(rank, count - jokers);
+                f(JOKER, jokers);
+            },
+        }
+    }
 }
 
-// first element is number of cards,
-pub type Move = Option<(u8, u8)>;
-pub type Hand = Vec<u8>;
+// Runs need at least this many cards.
+pub const MIN_RUN: u8 = 3;
 
 pub const THREE: u8 = 1;
 pub const FOUR : u8 = 2;
@@ -55,26 +274,135 @@ pub const ACE  : u8 = 12;
 pub const TWO  : u8 = 13;
 pub const JOKER: u8 = 14;
 
-pub const DECK: [u8; 54] = [
-    THREE, THREE, THREE, THREE,
-    FOUR,  FOUR,  FOUR,  FOUR,
-    FIVE,  FIVE,  FIVE,  FIVE,
-    SIX,   SIX,   SIX,   SIX,
-    SEVEN, SEVEN, SEVEN, SEVEN,
-    EIGHT, EIGHT, EIGHT, EIGHT,
-    NINE,  NINE,  NINE,  NINE,
-    TEN,   TEN,   TEN,   TEN,
-    JACK,  JACK,  JACK,  JACK,
-    QUEEN, QUEEN, QUEEN, QUEEN,
-    KING,  KING,  KING,  KING,
-    ACE,   ACE,   ACE,   ACE,
-    TWO,   TWO,   TWO,   TWO,
-    JOKER, JOKER,
+// The ranks above as a type, for reading and writing them. `rank as u8` is
+// the plain rank.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rank {
+    Three = 1,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+    Ace,
+    Two,
+    Joker,
+}
+
+const RANKS: [Rank; 14] = [
+    Rank::Three, Rank::Four, Rank::Five, Rank::Six, Rank::Seven, Rank::Eight,
+    Rank::Nine, Rank::Ten, Rank::Jack, Rank::Queen, Rank::King, Rank::Ace,
+    Rank::Two, Rank::Joker];
+
+impl Rank {
+    pub fn from_u8(rank: u8) -> Option<Rank> {
+        if THREE <= rank && rank <= JOKER {
+            Some(RANKS[rank as usize - 1])
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
+}
+
+// The rank lives in the high bits and the suit in the low two, so sorting a
+// hand sorts it by rank and comparing ranks is a shift.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Card(u8);
+
+impl Card {
+    pub fn new(rank: u8, suit: Suit) -> Card {
+        assert!(THREE <= rank && rank <= JOKER);
+        Card(rank << 2 | suit as u8)
+    }
+    pub fn rank(self) -> u8 { self.0 >> 2 }
+    pub fn typed_rank(self) -> Rank { RANKS[self.rank() as usize - 1] }
+    pub fn suit(self) -> Suit {
+        match self.0 & 3 {
+            0 => Suit::Clubs,
+            1 => Suit::Diamonds,
+            2 => Suit::Hearts,
+            _ => Suit::Spades,
+        }
+    }
+}
+
+// The black joker is a club and the red one a heart.
+pub const DECK: [Card; 54] = [
+    Card( 4), Card( 5), Card( 6), Card( 7),  // THREE
+    Card( 8), Card( 9), Card(10), Card(11),  // FOUR
+    Card(12), Card(13), Card(14), Card(15),  // FIVE
+    Card(16), Card(17), Card(18), Card(19),  // SIX
+    Card(20), Card(21), Card(22), Card(23),  // SEVEN
+    Card(24), Card(25), Card(26), Card(27),  // EIGHT
+    Card(28), Card(29), Card(30), Card(31),  // NINE
+    Card(32), Card(33), Card(34), Card(35),  // TEN
+    Card(36), Card(37), Card(38), Card(39),  // JACK
+    Card(40), Card(41), Card(42), Card(43),  // QUEEN
+    Card(44), Card(45), Card(46), Card(47),  // KING
+    Card(48), Card(49), Card(50), Card(51),  // ACE
+    Card(52), Card(53), Card(54), Card(55),  // TWO
+    Card(56), Card(58),                      // JOKER
 ];
 
+const SUITS: [Suit; 4] = [
+    Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
+
+// The cards a round is dealt from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeckSpec {
+    // Several standard decks, with or without their jokers.
+    Standard { decks: u8, jokers: bool },
+    // Any ranks. Repeated ranks get the next suit.
+    Custom(Vec<u8>),
+}
+
+impl DeckSpec {
+    // Enough standard decks with jokers for at least seven cards a player.
+    pub fn for_players(num_players: usize) -> DeckSpec {
+        let decks = max(1, (7 * num_players + DECK.len() - 1) / DECK.len());
+        DeckSpec::Standard {decks: decks as u8, jokers: true}
+    }
+
+    pub fn cards(&self) -> Vec<Card> {
+        match *self {
+            DeckSpec::Standard {decks, jokers} => {
+                let mut cards = Vec::with_capacity(decks as usize * DECK.len());
+                for _ in 0..decks {
+                    cards.extend(DECK.iter().cloned()
+                        .filter(|c| jokers || c.rank() != JOKER));
+                }
+                cards.sort();
+                cards
+            },
+            DeckSpec::Custom(ref ranks) => {
+                ranks.iter().enumerate().map(|(i, rank)| {
+                    let seen = ranks[..i].iter().filter(|r| *r == rank).count();
+                    Card::new(*rank, SUITS[seen % 4])
+                }).collect()
+            },
+        }
+    }
+}
+
 impl State {
-    pub fn new(num_players: usize, mut deck: Vec<u8>) -> State {
-        assert!(num_players <= 54);
+    pub fn new(num_players: usize, mut deck: Vec<Card>, rules: RuleSet)
+               -> State {
+        assert!(num_players <= deck.len() && num_players <= MAX_PLAYERS);
+        let mut cards = deck.clone();
+        cards.sort();
         let mut hands = Vec::with_capacity(num_players);
         for i in 0..num_players {
             let cards_left = deck.len();
@@ -84,20 +412,59 @@ impl State {
             hands.push(hand);
         }
         assert_eq!(0, deck.len());
-        let discard = Vec::with_capacity(54);
-        let next_player = (0..num_players as u8).collect();
-        State {hands: hands, discard: discard, next_player: next_player,
-               top_card: None, finishing_order: vec![]}
+        let discard = Vec::with_capacity(cards.len());
+        let core = CompactState::new(rules, &hands, &[]);
+        State {core: core, hands: hands, cards: cards, discard: discard,
+               exchanges: vec![], history: vec![]}
     }
 
-    pub fn realisation_from<R: Rng>(ps: &PartialState, rng: &mut R) -> State {
-        let mut deck = DECK.to_vec();
+    pub fn realisation_from<R: Rng>(ps: &PartialState, rng: &mut R)
+                                    -> Result<State, ScumError> {
+        if ps.hand_sizes.len() > MAX_PLAYERS {
+            return Err(ScumError::InconsistentPartialState(
+                "too many players"));
+        }
+        let mut deck = ps.cards.clone();
         for card in ps.hand.iter().chain(ps.discard.iter()) {
-            let pos = deck.iter().position(|c| *c == *card)
-                .expect("couldn't find card2");
+            let pos = try!(deck.iter().position(|c| *c == *card).ok_or(
+                ScumError::InconsistentPartialState("card was never dealt")));
+            deck.remove(pos);
+        }
+        // Cards we gave away are still with the receiver unless they have
+        // played them since. With several decks somebody else may have
+        // played a copy of the same card, so each card on the pile accounts
+        // for one gift at most, and only if the receiver could have played it.
+        let played_by = ps.discard_players();
+        let mut used = vec![false; ps.discard.len()];
+        let mut known: Vec<Hand> = vec![vec![]; ps.hand_sizes.len()];
+        for gift in ps.gave.iter() {
+            let receiver = gift.receiver;
+            for card in gift.cards.iter() {
+                let played = (0..ps.discard.len()).find(|&i| !used[i]
+                    && ps.discard[i] == *card
+                    && played_by[i].map_or(true, |p| p == receiver));
+                match played {
+                    Some(i) => used[i] = true,
+                    None => known[receiver as usize].push(*card),
+                }
+            }
+        }
+        for card in known.iter().flat_map(|h| h.iter()) {
+            let pos = try!(deck.iter().position(|c| *c == *card).ok_or(
+                ScumError::InconsistentPartialState("gift was never dealt")));
             deck.remove(pos);
         }
-        assert_eq!(deck.len(), 54 - ps.discard.len() - ps.hand.len());
+        // Every card nobody has seen has to end up in somebody's hand.
+        let unseen = ps.hand_sizes.iter().enumerate()
+            .filter(|&(i, _)| i != ps.player as usize)
+            .fold(0, |n, (_, size)| n + size);
+        let given = known.iter().fold(0, |n, k| n + k.len());
+        if known.iter().enumerate().any(|(i, k)| k.len() > ps.hand_sizes[i])
+                || unseen != deck.len() + given
+                || ps.hand_sizes[ps.player as usize] != ps.hand.len() {
+            return Err(ScumError::InconsistentPartialState(
+                "hand sizes don't match the cards left"));
+        }
         rng.shuffle(&mut deck);
         let mut hands = Vec::with_capacity(ps.hand_sizes.len());
         // println!("{:?}", ps.hand_sizes);
@@ -107,157 +474,850 @@ impl State {
             } else {
                 let cards_left = deck.len();
                 // println!("{:?} {:?}", size, deck.len());
-                let mut hand = deck.split_off(cards_left - *size);
+                let mut hand = deck.split_off(
+                    cards_left - (*size - known[i].len()));
+                hand.extend(known[i].iter().cloned());
                 hand.sort();
                 hands.push(hand);
             }
         }
         assert_eq!(0, deck.len());
-        State {
+        let mut exchanges = ps.gave.clone();
+        exchanges.extend(ps.received.iter().cloned());
+        let mut core = CompactState::new(ps.rules, &hands, &ps.discard);
+        core.top_card = ps.top_card;
+        core.top_player = ps.top_player;
+        core.passes = ps.passes as u8;
+        core.revolution = ps.revolution;
+        core.next_player.clear();
+        for player in ps.next_player.iter() {
+            core.next_player.push_back(*player);
+        }
+        for player in ps.finishing_order.iter() {
+            core.finish(*player);
+        }
+        core.rehash();
+        Ok(State {
+            core: core,
             hands: hands,
+            cards: ps.cards.clone(),
             discard: ps.discard.clone(),
-            next_player: ps.next_player.clone(),
-            top_card: ps.top_card.clone(),
-            finishing_order: ps.finishing_order.clone(),
-        }
+            exchanges: exchanges,
+            history: ps.history.clone(),
+        })
     }
     pub fn to_partial_state(&self) -> PartialState {
+        self.partial_state_for(self.current_player())
+    }
+    // What `player` can see, which need not be the player to move.
+    pub fn partial_state_for(&self, player: u8) -> PartialState {
         let hand_sizes = self.hands.iter().map(|h| h.len()).collect();
-        let player = self.current_player();
+        let gave = self.exchanges.iter()
+            .filter(|e| e.giver == player).cloned().collect();
+        let received = self.exchanges.iter()
+            .filter(|e| e.receiver == player).cloned().collect();
         PartialState {
+            rules: self.core.rules,
             player: player,
             hand_sizes: hand_sizes,
             hand: self.hands[player as usize].clone(),
+            cards: self.cards.clone(),
             discard: self.discard.clone(),
-            next_player: self.next_player.clone(),
-            top_card: self.top_card.clone(),
-            finishing_order: self.finishing_order.clone(),
+            next_player: self.core.next_player.to_vec().into_iter().collect(),
+            top_card: self.core.top_card,
+            top_player: self.core.top_player,
+            passes: self.core.passes as usize,
+            revolution: self.core.revolution,
+            finishing_order: self.finishing_order().to_vec(),
+            gave: gave,
+            received: received,
+            history: self.history.clone(),
         }
     }
+    // The rules-level part of the state, for searching and playouts.
+    pub fn compact(&self) -> CompactState { self.core }
+    // A hash of the position. Suits don't matter to the rules, so they don't
+    // change the hash.
+    pub fn zobrist(&self) -> u64 { self.core.zobrist() }
+
+    // Moves `cards` from the giver's hand to the receiver's before play. If
+    // the giver doesn't hold them all nothing is moved.
+    pub fn exchange(&mut self, giver: u8, receiver: u8, cards: Vec<Card>)
+                    -> Result<(), ScumError> {
+        assert!(self.discard.is_empty(), "exchanging cards after play began");
+        let mut hand = self.hands[giver as usize].clone();
+        for card in cards.iter() {
+            match hand.iter().position(|c| *c == *card) {
+                Some(pos) => { hand.remove(pos); },
+                None => return Err(ScumError::CardNotInHand(card.rank())),
+            }
+        }
+        self.hands[giver as usize] = hand;
+        for &card in cards.iter() {
+            self.hands[receiver as usize].push(card);
+            self.core.move_cards(Some(giver), Some(receiver), card.rank(), 1);
+        }
+        self.hands[receiver as usize].sort();
+        self.exchanges.push(
+            Exchange {giver: giver, receiver: receiver, cards: cards});
+        Ok(())
+    }
+    pub fn hand<'a>(&'a self, player: u8) -> &'a Hand {
+        &self.hands[player as usize]
+    }
 
     pub fn num_players(&self) -> usize { self.hands.len() }
-    pub fn moves(&self) -> Vec<Move> {
-        let player = self.next_player.front()
-            .expect("expected a next player in moves");
-        let hand = &self.hands[*player as usize];
-        if self.is_terminal() {
-            return vec![]
+    // Every card dealt this round, in order.
+    pub fn cards<'a>(&'a self) -> &'a [Card] { &self.cards[..] }
+    pub fn rules(&self) -> &RuleSet { &self.core.rules }
+    // Whether a revolution has inverted the rank order.
+    pub fn revolution(&self) -> bool { self.core.revolution }
+    pub fn moves(&self) -> Vec<Move> { self.core.moves() }
+    pub fn moves_into(&self, moves: &mut Vec<Move>) {
+        self.core.moves_into(moves)
+    }
+    pub fn is_terminal(&self) -> bool { self.core.is_terminal() }
+    pub fn current_player(&self) -> u8 { self.core.current_player() }
+    pub fn winner(&self) -> u8 { self.core.winner() }
+    // President first, Scum last once the round is over.
+    pub fn finishing_order(&self) -> &[u8] { self.core.finishing_order() }
+    // Whether the current player may make `muve`, without making it.
+    pub fn check_move(&self, muve: Move) -> Result<(), ScumError> {
+        self.core.check_move(muve)
+    }
+    // Makes `muve` for the current player. An illegal move leaves the state
+    // as it was. The Undo takes the move back again.
+    pub fn apply(&mut self, muve: Move) -> Result<Undo, ScumError> {
+        try!(self.core.check_move(muve));
+        let undo = self.core.undo_for(muve);
+        let player = self.core.current_player();
+        // A position read from text starts without the tricks before it.
+        if self.core.top_card.is_none() || self.history.is_empty() {
+            self.history.push(vec![]);
         }
-        match self.top_card {
-            None => all_moves(hand),
-            Some((count, card)) => moves(hand, count, card),
+        let turn = Turn {player: player, muve: muve};
+        self.history.last_mut().unwrap().push(turn);
+        if let Some(play) = muve {
+            play.each_rank(|rank, count| {
+                for _ in 0..count {
+                    self.play_card(player, rank);
+                }
+            });
         }
+        self.core.make_move(muve);
+        Ok(undo)
+    }
+    // Takes back the move `undo` came from. Moves have to be taken back
+    // latest first.
+    pub fn undo(&mut self, undo: Undo) {
+        if let Some(play) = undo.muve {
+            let mut played = 0;
+            play.each_rank(|_, count| played += count as usize);
+            let start = self.discard.len() - played;
+            let cards = self.discard.split_off(start);
+            let hand = &mut self.hands[undo.player as usize];
+            hand.extend(cards.into_iter());
+            hand.sort();
+        }
+        let emptied = match self.history.last_mut() {
+            Some(trick) => { trick.pop(); trick.is_empty() },
+            None => false,
+        };
+        if emptied {
+            self.history.pop();
+        }
+        self.core.undo(undo);
+    }
+    fn play_card(&mut self, player: u8, rank: u8) {
+        let hand = &mut self.hands[player as usize];
+        // println!("{:?} {:?} {:?}", player, rank, hand);
+        let pos = hand.iter().position(|c| c.rank() == rank)
+            .expect("couldn't find card");
+        let card = hand.remove(pos);
+        self.discard.push(card);
+    }
+    pub fn top_card<'a>(&'a self) -> &'a Move { &self.core.top_card }
+    // Every move so far, trick by trick.
+    pub fn history<'a>(&'a self) -> &'a [Vec<Turn>] { &self.history[..] }
+}
+
+// The players still to play in a trick, in order. A ring buffer rather than a
+// VecDeque so that it can be copied.
+#[derive(Copy)]
+struct Queue {
+    players: [u8; MAX_PLAYERS],
+    start: u8,
+    len: u8,
+    // The Zobrist keys of the line, kept up to date as it changes.
+    hash: u64,
+}
+
+impl Clone for Queue {
+    fn clone(&self) -> Queue { *self }
+}
+
+impl PartialEq for Queue {
+    fn eq(&self, other: &Queue) -> bool { self.to_vec() == other.to_vec() }
+}
+
+impl fmt::Debug for Queue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.to_vec())
+    }
+}
+
+impl Queue {
+    fn new() -> Queue {
+        Queue {players: [0; MAX_PLAYERS], start: 0, len: 0, hash: 0}
     }
+    fn len(&self) -> usize { self.len as usize }
+    fn get(&self, i: usize) -> u8 {
+        self.players[(self.start as usize + i) % MAX_PLAYERS]
+    }
+    fn front(&self) -> Option<u8> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(self.players[self.start as usize])
+        }
+    }
+    fn pop_front(&mut self) -> Option<u8> {
+        let front = self.front();
+        if let Some(player) = front {
+            self.hash ^= zobrist::queue_front(player);
+            if self.len > 1 {
+                let next = self.get(1);
+                self.hash ^= zobrist::queue_next(player, next)
+                    ^ zobrist::queue_front(next);
+            }
+            self.start = ((self.start as usize + 1) % MAX_PLAYERS) as u8;
+            self.len -= 1;
+        }
+        front
+    }
+    fn push_back(&mut self, player: u8) {
+        assert!(self.len() < MAX_PLAYERS, "too many players in the queue");
+        self.hash ^= if self.len == 0 {
+            zobrist::queue_front(player)
+        } else {
+            zobrist::queue_next(self.get(self.len() - 1), player)
+        };
+        let end = (self.start as usize + self.len()) % MAX_PLAYERS;
+        self.players[end] = player;
+        self.len += 1;
+    }
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.hash = 0;
+    }
+    fn copy_from(&mut self, other: &Queue) {
+        self.clear();
+        for i in 0..other.len() {
+            self.players[i] = other.get(i);
+        }
+        self.len = other.len;
+        self.hash = other.hash;
+    }
+    fn to_vec(&self) -> Vec<u8> {
+        (0..self.len())
+            .map(|i| self.players[(self.start as usize + i) % MAX_PLAYERS])
+            .collect()
+    }
+}
+
+// Everything about a round the rules care about: how many cards of each rank
+// everybody holds and whose turn it is, but not the suits or the discard
+// pile. It is Copy, so the search can play out thousands of games from it
+// without allocating.
+#[derive(Copy)]
+pub struct CompactState {
+    rules: RuleSet,
+    num_players: u8,
+    hands: [Counts; MAX_PLAYERS],
+    // How many cards everybody holds, so that is_out needn't add them up.
+    sizes: [u16; MAX_PLAYERS],
+    discard: Counts,
+    // The Zobrist keys of everything but the line of players, which keeps
+    // its own, kept up to date as the position changes.
+    hash: u64,
+    top_card: Move,
+    top_player: Option<u8>,
+    passes: u8,
+    revolution: bool,
+    next_player: Queue,
+    finishing_order: [u8; MAX_PLAYERS],
+    finished: u8,
+}
+
+impl Clone for CompactState {
+    fn clone(&self) -> CompactState { *self }
+}
+
+impl fmt::Debug for CompactState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CompactState {{ hands: {:?}, top_card: {:?}, \
+                   top_player: {:?}, passes: {}, revolution: {}, \
+                   next_player: {:?}, finishing_order: {:?} }}",
+               &self.hands[..self.num_players()], self.top_card,
+               self.top_player, self.passes, self.revolution,
+               self.next_player, self.finishing_order())
+    }
+}
+
+// What a move changed, so that it can be taken back. Only good for the state
+// it came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Undo {
+    muve: Move,
+    player: u8,
+    top_card: Move,
+    top_player: Option<u8>,
+    passes: u8,
+    revolution: bool,
+    next_player: Queue,
+    finished: u8,
+    hash: u64,
+}
+
+impl CompactState {
+    fn new(rules: RuleSet, hands: &[Hand], discard: &[Card]) -> CompactState {
+        assert!(hands.len() <= MAX_PLAYERS);
+        let mut state = CompactState {
+            rules: rules,
+            num_players: hands.len() as u8,
+            hands: [[0; 15]; MAX_PLAYERS],
+            sizes: [0; MAX_PLAYERS],
+            discard: counts(discard),
+            hash: 0,
+            top_card: None,
+            top_player: None,
+            passes: 0,
+            revolution: false,
+            next_player: Queue::new(),
+            finishing_order: [0; MAX_PLAYERS],
+            finished: 0,
+        };
+        for (i, hand) in hands.iter().enumerate() {
+            state.hands[i] = counts(hand);
+            state.sizes[i] = hand.len() as u16;
+            state.next_player.push_back(i as u8);
+        }
+        state.rehash();
+        state
+    }
+
+    // The hash of everything but the line of players, worked out from
+    // scratch.
+    fn full_hash(&self) -> u64 {
+        let mut hash = self.trick_hash();
+        for rank in THREE..JOKER + 1 {
+            for player in 0..self.num_players {
+                hash ^= zobrist::hand(
+                    player, rank, self.hands[player as usize][rank as usize]);
+            }
+            hash ^= zobrist::discard(rank, self.discard[rank as usize]);
+        }
+        for (i, player) in self.finishing_order().iter().enumerate() {
+            hash ^= zobrist::finished(i, *player);
+        }
+        hash
+    }
+    // The keys of the top card, who played it, the passes since and the
+    // revolution.
+    fn trick_hash(&self) -> u64 {
+        zobrist::top_card(self.top_card)
+            ^ zobrist::top_player(self.top_player)
+            ^ zobrist::passes(self.passes)
+            ^ zobrist::revolution(self.revolution)
+    }
+    // For after the fields have been set by hand.
+    fn rehash(&mut self) {
+        self.hash = self.full_hash();
+    }
+    // A hash of the whole position. Positions that play on the same way hash
+    // the same, whatever moves led to them.
+    pub fn zobrist(&self) -> u64 {
+        self.hash ^ self.next_player.hash
+    }
+    // Makes this a copy of `other`. Only the seats `other` has are copied,
+    // which for a small table is much less than the whole CompactState.
+    pub fn copy_from(&mut self, other: &CompactState) {
+        self.rules = other.rules;
+        self.num_players = other.num_players;
+        for player in 0..other.num_players() {
+            self.hands[player] = other.hands[player];
+            self.sizes[player] = other.sizes[player];
+        }
+        self.discard = other.discard;
+        self.hash = other.hash;
+        self.top_card = other.top_card;
+        self.top_player = other.top_player;
+        self.passes = other.passes;
+        self.revolution = other.revolution;
+        self.next_player.copy_from(&other.next_player);
+        for i in 0..other.finished as usize {
+            self.finishing_order[i] = other.finishing_order[i];
+        }
+        self.finished = other.finished;
+    }
+    // Whether the two are the same position, as far as `zobrist` goes.
+    pub fn same_position(&self, other: &CompactState) -> bool {
+        self.hands[..self.num_players()] == other.hands[..other.num_players()]
+            && self.discard == other.discard
+            && self.top_card == other.top_card
+            && self.top_player == other.top_player
+            && self.passes == other.passes
+            && self.revolution == other.revolution
+            && self.next_player == other.next_player
+            && self.finishing_order() == other.finishing_order()
+    }
+    // Moves cards from one hand to another, or between a hand and the
+    // discard pile (None), keeping the hash up to date.
+    fn move_cards(&mut self, from: Option<u8>, to: Option<u8>, rank: u8,
+                  count: u8) {
+        let (left, got) = (self.pile(from, rank), self.pile(to, rank));
+        self.set_pile(from, rank, left - count);
+        self.set_pile(to, rank, got + count);
+    }
+    fn pile(&self, place: Option<u8>, rank: u8) -> u8 {
+        match place {
+            Some(player) => self.hands[player as usize][rank as usize],
+            None => self.discard[rank as usize],
+        }
+    }
+    fn set_pile(&mut self, place: Option<u8>, rank: u8, count: u8) {
+        let key = |n| match place {
+            Some(player) => zobrist::hand(player, rank, n),
+            None => zobrist::discard(rank, n),
+        };
+        self.hash ^= key(self.pile(place, rank)) ^ key(count);
+        match place {
+            Some(player) => {
+                let p = player as usize;
+                self.sizes[p] = self.sizes[p] + count as u16
+                    - self.hands[p][rank as usize] as u16;
+                self.hands[p][rank as usize] = count;
+            },
+            None => self.discard[rank as usize] = count,
+        }
+    }
+
+    pub fn hand<'a>(&'a self, player: u8) -> &'a Counts {
+        &self.hands[player as usize]
+    }
+    // How many of each rank have been played.
+    pub fn discard<'a>(&'a self) -> &'a Counts { &self.discard }
+    pub fn num_players(&self) -> usize { self.num_players as usize }
+    pub fn rules(&self) -> &RuleSet { &self.rules }
+    pub fn revolution(&self) -> bool { self.revolution }
+    pub fn top_card<'a>(&'a self) -> &'a Move { &self.top_card }
     pub fn is_terminal(&self) -> bool {
-        !self.finishing_order.is_empty()
+        self.finished == self.num_players
     }
     pub fn current_player(&self) -> u8 {
-        *self.next_player.front()
-                    .expect("expected a next player in current_player")
+        self.next_player.front()
+            .expect("expected a next player in current_player")
     }
     pub fn winner(&self) -> u8 { self.finishing_order[0] }
-    pub fn apply(&mut self, muve: Move) {
-        let player = self.next_player.pop_front().expect("Ran out of players");
+    pub fn finishing_order(&self) -> &[u8] {
+        &self.finishing_order[..self.finished as usize]
+    }
+    pub fn moves(&self) -> Vec<Move> {
+        let mut moves = vec![];
+        self.moves_into(&mut moves);
+        moves
+    }
+    // Like `moves`, but reuses `moves` so that playouts don't allocate.
+    pub fn moves_into(&self, moves: &mut Vec<Move>) {
+        moves.clear();
+        if self.is_terminal() {
+            return;
+        }
+        let hand = &self.hands[self.current_player() as usize];
+        let rules = &self.rules;
+        match self.top_card.map(|top| top.effective()) {
+            None => all_moves(hand, rules, moves),
+            Some(Set(count, card)) =>
+                set_moves(hand, count, card, rules, self.revolution, moves),
+            Some(Run(length, low)) =>
+                run_moves(hand, length, low, rules, self.revolution, moves),
+            Some(Wild(..)) => unreachable!(),
+        }
+    }
+    pub fn check_move(&self, muve: Move) -> Result<(), ScumError> {
+        if self.is_terminal() {
+            return Err(ScumError::GameOver);
+        }
+        let play = match (muve, self.top_card) {
+            (Some(play), _) => play,
+            (None, Some(_)) => return Ok(()),
+            // Somebody has to lead.
+            (None, None) => return Err(ScumError::IllegalMove(muve)),
+        };
+        let rules = &self.rules;
+        let is_rank = |rank: u8| THREE <= rank && rank <= JOKER;
+        match play {
+            Set(0, _) => return Err(ScumError::IllegalCount(play)),
+            Set(count, JOKER) if count > 1 && rules.joker_beats_any_count =>
+                return Err(ScumError::IllegalCount(play)),
+            Set(_, rank) if !is_rank(rank) =>
+                return Err(ScumError::IllegalMove(muve)),
+            Run(length, low) if !rules.runs || length < MIN_RUN
+                    || low < THREE || low > TWO || length > TWO + 1 - low =>
+                return Err(ScumError::IllegalMove(muve)),
+            Wild(count, rank, jokers) if !rules.wild_jokers || jokers == 0
+                    || jokers >= count || !is_rank(rank) || rank == JOKER =>
+                return Err(ScumError::IllegalMove(muve)),
+            _ => {},
+        }
+        if let Some(top) = self.top_card {
+            try!(self.check_beats(play, top));
+        }
+        let hand = &self.hands[self.current_player() as usize];
+        let mut missing = None;
+        play.each_rank(|rank, count| {
+            if missing.is_none() && hand[rank as usize] < count {
+                missing = Some(rank);
+            }
+        });
+        match missing {
+            Some(rank) => Err(ScumError::CardNotInHand(rank)),
+            None => Ok(()),
+        }
+    }
+    pub fn apply(&mut self, muve: Move) -> Result<Undo, ScumError> {
+        try!(self.check_move(muve));
+        let undo = self.undo_for(muve);
+        self.make_move(muve);
+        Ok(undo)
+    }
+    pub fn undo(&mut self, undo: Undo) {
+        if let Some(play) = undo.muve {
+            let player = undo.player;
+            play.each_rank(|rank, count| {
+                self.move_cards(None, Some(player), rank, count)
+            });
+        }
+        self.top_card = undo.top_card;
+        self.top_player = undo.top_player;
+        self.passes = undo.passes;
+        self.revolution = undo.revolution;
+        self.next_player = undo.next_player;
+        // Anything past `finished` is never looked at.
+        self.finished = undo.finished;
+        self.hash = undo.hash;
+    }
+    fn undo_for(&self, muve: Move) -> Undo {
+        Undo {
+            muve: muve,
+            player: self.current_player(),
+            top_card: self.top_card,
+            top_player: self.top_player,
+            passes: self.passes,
+            revolution: self.revolution,
+            next_player: self.next_player,
+            finished: self.finished,
+            hash: self.hash,
+        }
+    }
+    // `apply` for moves known to be legal.
+    fn make_move(&mut self, muve: Move) {
+        let before = self.trick_hash();
+        self.play_move(muve);
+        self.hash ^= before ^ self.trick_hash();
+    }
+    fn play_move(&mut self, muve: Move) {
+        let player = self.next_player.pop_front().unwrap();
         match muve {
-            Some((count, card)) => {
-                assert!(self.top_card.is_none()
-                    || self.top_card.unwrap().0 == count
-                    || card == JOKER);
-                assert!(self.top_card.is_none()
-                    || self.top_card.unwrap().1 < card);
-                self.next_player.push_back(player);
-                for _ in 0..count {
-                    self.play_card(player, card);
+            Some(play) => {
+                let equal = match (play.effective(),
+                                   self.top_card.map(|top| top.effective())) {
+                    (Set(_, rank), Some(Set(_, top))) => rank == top,
+                    (Run(_, low), Some(Run(_, top))) => low == top,
+                    _ => false,
+                };
+                play.each_rank(|rank, count| {
+                    self.move_cards(Some(player), None, rank, count)
+                });
+                if let Set(count, rank) = play.effective() {
+                    if self.rules.revolution && count >= 4 && rank != JOKER {
+                        self.revolution = !self.revolution;
+                    }
+                }
+                self.top_card = muve;
+                self.top_player = Some(player);
+                self.passes = 0;
+                if self.is_out(player) {
+                    self.finish(player);
+                    if self.rules.end_on_first_finisher {
+                        self.rank_remaining_players(player);
+                        return;
+                    }
+                    if self.finished + 1 == self.num_players {
+                        // Nobody is left to answer the play.
+                        self.start_trick(player);
+                        return;
+                    }
+                } else {
+                    self.next_player.push_back(player);
+                }
+                let burns = match play.effective() {
+                    Set(_, rank) => self.rules.stop_card == Some(rank),
+                    _ => false,
+                };
+                if burns {
+                    // The pile is burnt and the player leads again, or the
+                    // next player does if they just went out.
+                    self.start_trick(player);
+                    return;
                 }
-                if self.hands[player as usize].is_empty() {
-                    self.finishing_order.push(player);
+                if equal && self.rules.skip_on_equal
+                        && self.next_player.len() > 1 {
+                    let skipped = self.next_player.pop_front().unwrap();
+                    self.next_player.push_back(skipped);
+                }
+            },
+            None => {
+                self.passes += 1;
+                if !self.rules.pass_locks_out {
+                    self.next_player.push_back(player);
                 }
-                self.top_card = muve
             },
-            None => {},  // Pass, no nothing.
         }
-        if self.next_player.len() == 1 {
-            assert!(muve.is_none(), "state: {:?}, player: {}", &self, player);
-            let player = self.next_player.pop_front().unwrap();
-            let num_players = self.num_players() as u8;
-            self.next_player.extend(
-                (player..player + num_players).map(|p| p % num_players));
-            self.top_card = None;
-            return;
+        // A player who went out on top has left the trick, and everybody
+        // still in it gets to answer their play.
+        let on_top = self.top_player.map_or(false, |p| !self.is_out(p));
+        let trick_over = if self.rules.pass_locks_out {
+            // Everybody else passed.
+            self.next_player.len() <= on_top as usize
+        } else {
+            // Everybody but the player on top passed in a row.
+            self.passes as usize + on_top as usize >= self.next_player.len()
+        };
+        if trick_over {
+            let leader = match self.top_player {
+                // The lead goes round from the seat of whoever went out.
+                Some(top) if !on_top => top,
+                _ => self.next_player.pop_front().unwrap_or(player),
+            };
+            self.start_trick(leader);
         }
     }
-    fn play_card(&mut self, player: u8, card: u8) {
-        let hand = &mut self.hands[player as usize];
-        // println!("{:?} {:?} {:?}", player, card, hand);
-        let pos = hand.iter().position(|c| *c == card)
-            .expect("couldn't find card");
-        hand.remove(pos);
-        self.discard.push(card);
+    fn check_beats(&self, play: Play, top: Play) -> Result<(), ScumError> {
+        let rules = &self.rules;
+        let (count_ok, rank_ok) = match (play.effective(), top.effective()) {
+            (Set(count, rank), Set(top_count, top_rank)) =>
+                (top_count == count
+                 || (!rules.counts_must_match && top_count < count)
+                 || (rank == JOKER && rules.joker_beats_any_count),
+                 beats(rank, top_rank, self.revolution, rules.play_equal)),
+            (Set(1, JOKER), Run(..)) if rules.joker_beats_any_count =>
+                (true, true),
+            (Run(length, low), Run(top_length, top_low)) =>
+                (length == top_length,
+                 beats(low, top_low, self.revolution, rules.play_equal)),
+            _ => return Err(ScumError::IllegalMove(Some(play))),
+        };
+        if !count_ok {
+            Err(ScumError::IllegalCount(play))
+        } else if !rank_ok {
+            Err(ScumError::RankTooLow(play))
+        } else {
+            Ok(())
+        }
+    }
+    fn is_out(&self, player: u8) -> bool {
+        self.sizes[player as usize] == 0
+    }
+    fn finish(&mut self, player: u8) {
+        self.hash ^= zobrist::finished(self.finished as usize, player);
+        self.finishing_order[self.finished as usize] = player;
+        self.finished += 1;
+    }
+    // Ends the round early: the players still holding cards are ranked by
+    // how many they have left, ties going in seat order.
+    fn rank_remaining_players(&mut self, finisher: u8) {
+        let num_players = self.num_players;
+        let mut rest: Vec<u8> = (finisher + 1..finisher + num_players)
+            .map(|p| p % num_players)
+            .filter(|p| !self.is_out(*p))
+            .collect();
+        {
+            let sizes = &self.sizes;
+            rest.sort_by(|a, b| sizes[*a as usize].cmp(&sizes[*b as usize]));
+        }
+        for player in rest.into_iter() {
+            self.finish(player);
+        }
+        self.next_player.clear();
+    }
+    // Starts a new trick with `leader`, skipping players that are out. When
+    // only one player still holds cards they are the Scum and the round ends.
+    fn start_trick(&mut self, leader: u8) {
+        let num_players = self.num_players;
+        self.next_player.clear();
+        for player in (leader..leader + num_players).map(|p| p % num_players) {
+            if !self.is_out(player) {
+                self.next_player.push_back(player);
+            }
+        }
+        self.top_card = None;
+        self.top_player = None;
+        self.passes = 0;
+        if self.next_player.len() == 1 {
+            let scum = self.next_player.pop_front().unwrap();
+            self.finish(scum);
+        }
     }
-    pub fn top_card<'a>(&'a self) -> &'a Move { &self.top_card }
 }
 
 pub fn play_randomly<R>(state: &mut State, rng: &mut R) where R: Rng {
+    let mut moves = Vec::with_capacity(32);
+    while !state.is_terminal() {
+        state.moves_into(&mut moves);
+        let action = *rng.choose(&moves[..]).unwrap();
+        state.apply(action).ok().expect("generated an illegal move");
+    }
+}
+
+// play_randomly for rollouts: no cards are moved around and nothing is
+// allocated per move. Nobody looks the played out position up, so the keys
+// of the trick are left out of its hash, which is stale afterwards.
+pub fn playout<R: Rng>(state: &mut CompactState, rng: &mut R) {
+    let mut moves = Vec::with_capacity(32);
     while !state.is_terminal() {
-        let action = *rng.choose(&mut state.moves()[..]).unwrap();
-        state.apply(action);
+        state.moves_into(&mut moves);
+        let action = *rng.choose(&moves[..]).unwrap();
+        state.play_move(action);
     }
 }
 
-fn moves(hand: &Hand, count: u8, card: u8) -> Vec<Move> {
-    let mut moves = Vec::with_capacity(hand.len() / count as usize + 2);
+// Plays `state` out in `scratch`, leaving `state` as it was, and returns the
+// winner. Searches keep one scratch state for all their rollouts, so only
+// the seats in use get copied rather than room for MAX_PLAYERS.
+pub fn rollout<R: Rng>(state: &CompactState, scratch: &mut CompactState,
+                       rng: &mut R) -> u8 {
+    scratch.copy_from(state);
+    playout(scratch, rng);
+    scratch.winner()
+}
+
+// Whether `card` may be played on `top`. Jokers stay on top in a revolution.
+fn beats(card: u8, top: u8, revolution: bool, equal: bool) -> bool {
+    if card == top {
+        equal
+    } else if card == JOKER || top == JOKER {
+        card == JOKER
+    } else if revolution {
+        card < top
+    } else {
+        card > top
+    }
+}
+
+// The moves answering `count` cards of rank `card`, passing first.
+fn set_moves(hand: &Counts, count: u8, card: u8, rules: &RuleSet,
+             revolution: bool, moves: &mut Vec<Move>) {
     moves.push(None);
-    let mut i = hand.len();
-    let offset = count as usize - 1;
-    let mut has_joker = false;
-    while i > 0 {
-        i -= 1;
-        if hand[i] <= card {
+    let jokers = wild_jokers(hand, rules);
+    // Walk the sets of equal cards from the highest down.
+    for rank in (THREE..JOKER + 1).rev() {
+        let have = hand[rank as usize];
+        if have == 0 {
+            continue;
+        }
+        if !revolution
+                && (rank < card || (rank == card && !rules.play_equal)) {
             break;
         }
-        if hand[i] == JOKER {
-            if !has_joker {
-                moves.push(Some((1, JOKER)));
-                has_joker = true;
-            }
+        if !beats(rank, card, revolution, rules.play_equal) {
             continue;
         }
-        if i < offset {
-            break;
+        let wild = if rank == JOKER { 0 } else { jokers };
+        if rank == JOKER && rules.joker_beats_any_count {
+            moves.push(Some(Set(1, JOKER)));
+        } else if rules.counts_must_match {
+            if have + wild >= count {
+                moves.push(Some(set_of(count, rank, have)));
+            }
+        } else {
+            for n in count..have + wild + 1 {
+                moves.push(Some(set_of(n, rank, have)));
+            }
         }
-        if hand[i] == hand[i - offset] {
-            let card = hand[i];
-            moves.push(Some((count, card)));
-            i -= offset;
-            while i > 0 && hand[i - 1] == card { i -= 1; }
+    }
+}
+
+// How many jokers in `hand` can complete sets of other ranks.
+fn wild_jokers(hand: &Counts, rules: &RuleSet) -> u8 {
+    if rules.wild_jokers { hand[JOKER as usize] } else { 0 }
+}
+
+// `count` cards of `rank` when holding `have` of them, using as few jokers as
+// possible.
+fn set_of(count: u8, rank: u8, have: u8) -> Play {
+    if count <= have {
+        Set(count, rank)
+    } else {
+        Wild(count, rank, count - have)
+    }
+}
+
+fn run_moves(hand: &Counts, length: u8, low: u8, rules: &RuleSet,
+             revolution: bool, moves: &mut Vec<Move>) {
+    moves.push(None);
+    if hand[JOKER as usize] > 0 && rules.joker_beats_any_count {
+        moves.push(Some(Set(1, JOKER)));
+    }
+    let lengths = run_lengths(hand);
+    for start in (THREE..JOKER).rev() {
+        if lengths[start as usize] >= length
+                && beats(start, low, revolution, rules.play_equal) {
+            moves.push(Some(Run(length, start)));
         }
     }
-    moves
 }
 
-fn all_moves(hand: &Hand) -> Vec<Move> {
-    if hand.is_empty() {
-        return vec![None]
+// The length of the longest run in `hand` starting at each rank.
+fn run_lengths(hand: &Counts) -> [u8; 15] {
+    let mut lengths = [0; 15];
+    for rank in (THREE..JOKER).rev() {
+        if hand[rank as usize] > 0 {
+            lengths[rank as usize] = 1 + lengths[rank as usize + 1];
+        }
     }
-    let mut moves = vec![];
-    let mut i = 0;
-    let end = hand.len();
-    while i < end {
-        if hand[i] == JOKER {
-            moves.push(Some((1, JOKER)));
+    lengths
+}
+
+fn all_moves(hand: &Counts, rules: &RuleSet, moves: &mut Vec<Move>) {
+    if hand.iter().all(|n| *n == 0) {
+        moves.push(None);
+        return;
+    }
+    let jokers = wild_jokers(hand, rules);
+    for rank in THREE..JOKER + 1 {
+        let have = hand[rank as usize];
+        if have == 0 {
+            continue;
+        }
+        if rank == JOKER && rules.joker_beats_any_count {
+            moves.push(Some(Set(1, JOKER)));
             break;
         }
-        for off in 0..5 {
-            let j = i + off;
-            if j < end && hand[i] == hand[j] {
-                moves.push(Some((off as u8 + 1, hand[i])));
-            } else {
-                i = j;
-                break;
+        let wild = if rank == JOKER { 0 } else { jokers };
+        for count in 1..have + wild + 1 {
+            moves.push(Some(set_of(count, rank, have)));
+        }
+    }
+    if rules.runs {
+        let lengths = run_lengths(hand);
+        for low in THREE..JOKER {
+            for length in MIN_RUN..lengths[low as usize] + 1 {
+                moves.push(Some(Run(length, low)));
             }
         }
     }
-    moves
 }
 
 const NOBODY: u8 = -1;
@@ -285,37 +1345,52 @@ impl Node {
         }).unwrap()
     }
 
-    pub fn add_child<R:Rng>(&mut self, state: &mut State, rng: &mut R) {
+    // Expands one untried move and plays it out. Returns the winner.
+    pub fn add_child<R:Rng>(&mut self, state: &mut CompactState,
+                            scratch: &mut CompactState, rng: &mut R) -> u8 {
         let player = state.current_player();
         let move_ = self.untried_moves.pop()
             .expect("tried to pop untried move");
-        state.apply(move_);
+        let undo = state.apply(move_).ok().expect("generated an illegal move");
         let mut moves = state.moves();
         rng.shuffle(&mut moves);
         let mut node = Node::new(player, moves);
-        play_randomly(state, rng);
-        node.update(state);
+        let winner = rollout(state, scratch, rng);
+        state.undo(undo);
+        node.update(winner);
         self.children.push((move_, node));
+        winner
     }
 
-    pub fn update(&mut self, state: &mut State) {
+    pub fn update(&mut self, winner: u8) {
         self.plays += 1.;
-        if state.winner() == self.player {
+        if winner == self.player {
             self.wins += 1.;
         }
     }
 
-    pub fn uct<R: Rng>(&mut self, state: &mut State, rng: &mut R) {
-        if self.untried_moves.is_empty() && !self.children.is_empty() {
+    // One iteration of the search. `state` is walked down the tree and back
+    // again, so it is unchanged afterwards, and the playout is played in
+    // `scratch`. Returns the playout's winner.
+    pub fn uct<R: Rng>(&mut self, state: &mut CompactState,
+                       scratch: &mut CompactState, rng: &mut R) -> u8 {
+        let winner = if self.untried_moves.is_empty()
+                && !self.children.is_empty() {
             let i = self.select_child();
             let &mut (move_, ref mut child) = &mut self.children[i];
             assert_eq!(child.player, state.current_player());
-            state.apply(move_);
-            child.uct(state, rng);
+            let undo = state.apply(move_)
+                .ok().expect("generated an illegal move");
+            let winner = child.uct(state, scratch, rng);
+            state.undo(undo);
+            winner
         } else if !self.untried_moves.is_empty() {
-            self.add_child(state, rng);
-        }
-        self.update(state);
+            self.add_child(state, scratch, rng)
+        } else {
+            state.winner()
+        };
+        self.update(winner);
+        winner
     }
 
     pub fn tree_string(&self) -> String {
@@ -346,27 +1421,36 @@ impl Node {
 pub fn best_move<R: Rng>(
     partial: &PartialState, reals: usize, iters: usize, rng: &mut R) -> Move {
 
-    let mut outcomes: HashMap<Move, usize> = HashMap::new();
+    best_move_with(partial, reals, iters, rng,
+                   |p, rng| State::realisation_from(p, rng))
+}
+
+// Like `best_move`, but `realise` makes the realisations.
+pub fn best_move_with<R, F>(partial: &PartialState, reals: usize, iters: usize,
+                            rng: &mut R, mut realise: F) -> Move
+    where R: Rng,
+          F: FnMut(&PartialState, &mut R) -> Result<State, ScumError> {
+
+    // A Vec rather than a HashMap, whose order changes from run to run, so
+    // that ties go the same way every time.
+    let mut outcomes: Vec<(Move, usize)> = vec![];
     for r in 0..reals {
-        let state = State::realisation_from(partial, rng);
+        let mut state = realise(partial, rng)
+            .ok().expect("inconsistent partial state").compact();
         let mut moves = state.moves();
         if moves.len() == 1 {
             return moves[0];
         }
         rng.shuffle(&mut moves);
         let mut root = Node::new(NOBODY, moves);
+        let mut scratch = state;
         for _ in 0..iters {
-            root.uct(&mut state.clone(), rng);
+            root.uct(&mut state, &mut scratch, rng);
         }
         for &(ref move_, ref node) in root.children.iter() {
-            match outcomes.entry(*move_) {
-                Occupied(mut o) => {
-                    let old = *o.get();
-                    o.insert(old + node.plays as usize);
-                },
-                Vacant(v) => {
-                    v.insert(node.plays as usize);
-                },
+            match outcomes.iter().position(|o| o.0 == *move_) {
+                Some(i) => outcomes[i].1 += node.plays as usize,
+                None => outcomes.push((*move_, node.plays as usize)),
             }
         }
         if r == 0 && partial.hand.len() <= 3 {
@@ -376,33 +1460,66 @@ pub fn best_move<R: Rng>(
     if partial.hand.len() <= 3 {
         debug!("{:?}", outcomes);
     }
-    *outcomes.iter().max_by(|c| *c.1 as i64).unwrap().0
+    outcomes.iter().max_by(|c| c.1).unwrap().0
 }
 
 pub trait Player {
     fn choose_move(&mut self, s: State) -> Move;
+
+    // Picks `count` cards that `player` hands back to the Scum. By default
+    // those are the lowest cards.
+    fn choose_gift(&mut self, s: State, player: u8, count: usize)
+                   -> Vec<Card> {
+        s.hands[player as usize][..count].to_vec()
+    }
+
+    // Players that use randomness take it from here on, so that a game can
+    // be played again with the same decisions.
+    fn seed(&mut self, _seed: u64) {}
 }
 
 pub trait FairPlayer {
     fn choose_move(&mut self, p: PartialState) -> Move;
+
+    fn choose_gift(&mut self, p: PartialState, count: usize) -> Vec<Card> {
+        p.hand[..count].to_vec()
+    }
+
+    fn seed(&mut self, _seed: u64) {}
 }
 
 impl<T: FairPlayer> Player for T {
     fn choose_move(&mut self, s: State) -> Move {
         self.choose_move(s.to_partial_state())
     }
+
+    fn choose_gift(&mut self, s: State, player: u8, count: usize)
+                   -> Vec<Card> {
+        FairPlayer::choose_gift(self, s.partial_state_for(player), count)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        FairPlayer::seed(self, seed)
+    }
 }
 
 pub struct CheatingUCT {
     rng: XorShiftRng,
     iters: usize,
+    transpositions: bool,
 }
 
 use rand::weak_rng;
 
 impl CheatingUCT {
     pub fn new(iters: usize) -> CheatingUCT {
-        CheatingUCT {rng: weak_rng(), iters: iters}
+        CheatingUCT {rng: weak_rng(), iters: iters, transpositions: false}
+    }
+
+    // Searches positions instead of move sequences, sharing what it learns
+    // about a position however it was reached.
+    pub fn with_transpositions(iters: usize) -> CheatingUCT {
+        CheatingUCT {rng: weak_rng(), iters: iters, transpositions: true}
     }
 }
 
@@ -412,30 +1529,76 @@ impl Player for CheatingUCT {
         if moves.len() == 1 {
             return moves[0];
         }
+        let mut state = s.compact();
+        if self.transpositions {
+            let mut search = TranspositionSearch::new(&state, &mut self.rng);
+            for _ in 0..self.iters {
+                search.iterate(&mut state, &mut self.rng);
+            }
+            return search.best_move();
+        }
         self.rng.shuffle(&mut moves);
         let mut root = Node::new(NOBODY, moves);
+        let mut scratch = state;
         for _ in 0..self.iters {
-            root.uct(&mut s.clone(), &mut self.rng);
+            root.uct(&mut state, &mut scratch, &mut self.rng);
         }
         root.children.iter().max_by(|c| c.1.plays as usize).unwrap().0
     }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed);
+    }
 }
 
 pub struct FairUCT {
     rng: XorShiftRng,
     reals: usize,
     iters: usize,
+    inference: Option<Inference>,
+    belief: Option<Belief>,
 }
 
 impl FairUCT {
     pub fn new(reals: usize, iters: usize) -> FairUCT {
-        FairUCT {rng: weak_rng(), reals: reals, iters: iters}
+        FairUCT {rng: weak_rng(), reals: reals, iters: iters, inference: None,
+                 belief: None}
+    }
+
+    // Realises hands that fit what the others have played, see Inference.
+    pub fn with_inference(reals: usize, iters: usize, inference: Inference)
+                          -> FairUCT {
+        FairUCT {rng: weak_rng(), reals: reals, iters: iters,
+                 inference: Some(inference), belief: None}
+    }
+
+    // Draws the realisations from a Belief of `particles` deals that is kept
+    // up to date over the round.
+    pub fn with_belief(reals: usize, iters: usize, particles: usize,
+                       inference: Inference) -> FairUCT {
+        FairUCT {rng: weak_rng(), reals: reals, iters: iters, inference: None,
+                 belief: Some(Belief::new(particles, inference))}
     }
 }
 
 impl FairPlayer for FairUCT {
     fn choose_move(&mut self, p: PartialState) -> Move {
-        best_move(&p, self.reals, self.iters, &mut self.rng)
+        if let Some(ref mut belief) = self.belief {
+            belief.update(&p, &mut self.rng);
+            let belief = &*belief;
+            return best_move_with(&p, self.reals, self.iters, &mut self.rng,
+                                  |_, rng| Ok(belief.realisation(rng)));
+        }
+        match self.inference {
+            Some(inference) =>
+                best_move_with(&p, self.reals, self.iters, &mut self.rng,
+                               |p, rng| inference.realisation(p, rng)),
+            None => best_move(&p, self.reals, self.iters, &mut self.rng),
+        }
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed);
     }
 }
 
@@ -445,120 +1608,683 @@ use std::old_io;
 
 impl FairPlayer for ConsolePlayer {
     fn choose_move(&mut self, p: PartialState) -> Move {
-        static CARDS: [&'static str; 15] = ["",
-            "3", "4", "5", "6", "7", "8", "9", "10", "Jack",
-            "Queen", "King", "Ace", "2", "Joker"];
-
-        match p.top_card {
-            Some((count, card)) =>
-                println!("Top card: {}x {}", count, CARDS[card as usize]),
-            None => println!("Play whatever you want :)"),
+        match (p.top_card, p.history.last()) {
+            (Some(_), Some(trick)) => println!("This trick: {}",
+                                               trick_string(trick)),
+            (Some(play), None) => println!("Top card: {}", play),
+            (None, _) => println!("Play whatever you want :)"),
         };
         print!("Your cards:");
         for c in p.hand.iter() {
-            print!(" {}", CARDS[*c as usize]);
+            print!(" {}", c.typed_rank());
         } println!("");
         // Dirty hack for getting the moves.
         let mut move_ = None;
         while {
             println!("Possible Moves:");
-            let moves = State::realisation_from(&p, &mut rand::weak_rng()).moves();
+            let moves = State::realisation_from(&p, &mut rand::weak_rng())
+                .ok().expect("inconsistent partial state").moves();
             for (i, m) in moves.iter().enumerate() {
-                match m {
-                    &Some((count, card)) =>
-                        println!("{}: {}x {}", i, count, CARDS[card as usize]),
-                    &None => println!("{}: Pass", i),
-                }
+                println!("{}: {}", i, move_string(*m));
             }
-            println!("INPUT:");
+            println!("INPUT (or \"history\"):");
             let mut reader = old_io::stdin();
             let input = reader.read_line().ok().expect("Failed to read line");
             println!("YOU TYPED:");
             println!("{}", input);
-            let res = FromStr::from_str(input.trim())
-                .map_err(|e| format!("{:?}", e))
-                .and_then(|i| moves.get(i).map(|m| move_ = *m)
-                    .ok_or("Not a valid move".to_string()))
-                .map_err(|err| println!("{:?}", err));
+            let res = if input.trim() == "history" {
+                for (i, trick) in p.history.iter().enumerate() {
+                    println!("{}. {}", i + 1, trick_string(trick));
+                }
+                Err(())
+            } else {
+                // Either the number of a move or the move itself, e.g. 2xQ.
+                match FromStr::from_str(input.trim()) {
+                    Ok(i) => moves.get(i).cloned()
+                        .ok_or("Not a valid move".to_string()),
+                    Err(_) => parse_move(&input[..])
+                        .map_err(|e| format!("{}", e))
+                        .and_then(|m| if moves.contains(&m) {
+                            Ok(m)
+                        } else {
+                            Err("Not a valid move".to_string())
+                        }),
+                }.map(|m| move_ = m).map_err(|err| println!("{}", err))
+            };
             res.is_err()
         } {}
         move_
     }
+
+    fn choose_gift(&mut self, p: PartialState, count: usize) -> Vec<Card> {
+        for e in p.received.iter() {
+            print!("#{} gave you:", e.giver);
+            for c in e.cards.iter() {
+                print!(" {}", c.typed_rank());
+            } println!("");
+        }
+        println!("Pick {} cards to give back:", count);
+        for (i, c) in p.hand.iter().enumerate() {
+            println!("{}: {}", i, c.typed_rank());
+        }
+        loop {
+            println!("INPUT:");
+            let mut reader = old_io::stdin();
+            let input = reader.read_line().ok().expect("Failed to read line");
+            let mut picked: Vec<usize> = input.trim().split(' ')
+                .filter(|s| !s.is_empty())
+                .filter_map(|s| FromStr::from_str(s).ok())
+                .filter(|i| *i < p.hand.len())
+                .collect();
+            picked.sort();
+            picked.dedup();
+            if picked.len() == count {
+                return picked.iter().map(|i| p.hand[*i]).collect();
+            }
+            println!("Please pick {} different cards.", count);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::{all_moves, moves};
+    use super::Play::{Run, Set, Wild};
+
+    use rand::{Rng, weak_rng};
+    use std::default::Default;
 
     #[allow(non_snake_case)]
-    fn M(count: u8, card: u8) -> Move { Some((count, card)) }
+    fn M(count: u8, card: u8) -> Move { Some(Set(count, card)) }
+
+    pub fn deal(ranks: &[u8]) -> Vec<Card> {
+        DeckSpec::Custom(ranks.to_vec()).cards()
+    }
+
+    // Deals DECK out to `players` and plays random moves to the end of the
+    // round. Before every move `f` gets the state and the move about to be
+    // made, and has to leave the state as it found it.
+    pub fn random_game<R, F>(rules: RuleSet, players: usize, rng: &mut R,
+                             mut f: F) -> State
+        where R: Rng, F: FnMut(&mut State, Move, &mut R) {
+        let mut deck = DECK.to_vec();
+        rng.shuffle(&mut deck[..]);
+        let mut state = State::new(players, deck, rules);
+        while !state.is_terminal() {
+            let muve = *rng.choose(&state.moves()[..]).unwrap();
+            f(&mut state, muve, rng);
+            state.apply(muve).unwrap();
+        }
+        state
+    }
+
+    fn moves(hand: &Hand, count: u8, card: u8, rules: &RuleSet,
+             revolution: bool) -> Vec<Move> {
+        let mut moves = vec![];
+        super::set_moves(&counts(hand), count, card, rules, revolution,
+                         &mut moves);
+        moves
+    }
+
+    fn run_moves(hand: &Hand, length: u8, low: u8, rules: &RuleSet,
+                 revolution: bool) -> Vec<Move> {
+        let mut moves = vec![];
+        super::run_moves(&counts(hand), length, low, rules, revolution,
+                         &mut moves);
+        moves
+    }
+
+    fn all_moves(hand: &Hand, rules: &RuleSet) -> Vec<Move> {
+        let mut moves = vec![];
+        super::all_moves(&counts(hand), rules, &mut moves);
+        moves
+    }
 
     #[test]
     fn test_moves() {
-        let hand = vec![THREE, FOUR, FOUR, FOUR, FOUR, JOKER, JOKER];
+        let rules = RuleSet::default();
+        let hand = deal(&[THREE, FOUR, FOUR, FOUR, FOUR, JOKER, JOKER]);
 
         assert_eq!(
-            vec![None, M(1, JOKER), M(1, FOUR)], moves(&hand, 1, THREE));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 1, FOUR));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 1, FIVE));
+            vec![None, M(1, JOKER), M(1, FOUR)],
+            moves(&hand, 1, THREE, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 1, FOUR, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 1, FIVE, &rules, false));
 
         assert_eq!(
-            vec![None, M(1, JOKER), M(2, FOUR)], moves(&hand, 2, THREE));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 2, FOUR));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 2, FIVE));
+            vec![None, M(1, JOKER), M(2, FOUR)],
+            moves(&hand, 2, THREE, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 2, FOUR, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 2, FIVE, &rules, false));
 
         assert_eq!(
-            vec![None, M(1, JOKER), M(3, FOUR)], moves(&hand, 3, THREE));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 3, FOUR));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 3, FIVE));
+            vec![None, M(1, JOKER), M(3, FOUR)],
+            moves(&hand, 3, THREE, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 3, FOUR, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 3, FIVE, &rules, false));
 
         assert_eq!(
-            vec![None, M(1, JOKER), M(4, FOUR)], moves(&hand, 4, THREE));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 4, FOUR));
-        assert_eq!(vec![None, M(1, JOKER)], moves(&hand, 4, FIVE));
+            vec![None, M(1, JOKER), M(4, FOUR)],
+            moves(&hand, 4, THREE, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 4, FOUR, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER)],
+            moves(&hand, 4, FIVE, &rules, false));
     }
 
     #[test]
     // Regression test from playing with the test client.
     fn test_moves_close() {
-        let hand = vec![1, 9, 11, 12, 13];
+        let rules = RuleSet::default();
+        let hand = deal(&[1, 9, 11, 12, 13]);
         let top_card = 11;
-        assert_eq!(vec![None, Some((1, 13)), Some((1, 12))],
-            moves(&hand, 1, top_card));
+        assert_eq!(vec![None, Some(Set(1, 13)), Some(Set(1, 12))],
+            moves(&hand, 1, top_card, &rules, false));
     }
 
     #[test]
     // Regression test from playing with the test client.
     fn test_moves_duplicate_move() {
-        let hand = vec![7, 7, 9, 10, 11, 12, 13];
+        let rules = RuleSet::default();
+        let hand = deal(&[7, 7, 9, 10, 11, 12, 13]);
         let top_card = 4;
-        assert_eq!(vec![None, Some((1, 13)), Some((1, 12)), Some((1, 11)),
-            Some((1, 10)), Some((1, 9)), Some((1, 7))],
-            moves(&hand, 1, top_card));
+        assert_eq!(vec![None, Some(Set(1, 13)), Some(Set(1, 12)),
+            Some(Set(1, 11)), Some(Set(1, 10)), Some(Set(1, 9)),
+            Some(Set(1, 7))],
+            moves(&hand, 1, top_card, &rules, false));
     }
 
     #[test]
     fn test_all_moves() {
-        let hand = vec![THREE, FOUR, FOUR, FOUR, FOUR, JOKER, JOKER];
+        let rules = RuleSet::default();
+        let hand = deal(&[THREE, FOUR, FOUR, FOUR, FOUR, JOKER, JOKER]);
 
         assert_eq!(vec![
             M(1, THREE),
             M(1, FOUR), M(2, FOUR), M(3, FOUR), M(4, FOUR),
             M(1, JOKER)
-        ], all_moves(&hand));
+        ], all_moves(&hand, &rules));
+    }
+
+    #[test]
+    fn test_moves_counts_need_not_match() {
+        let rules = RuleSet {counts_must_match: false, .. RuleSet::default()};
+        let hand = deal(&[FOUR, FOUR, FOUR, FIVE, FIVE]);
+        assert_eq!(vec![None, M(1, FIVE), M(2, FIVE),
+                        M(1, FOUR), M(2, FOUR), M(3, FOUR)],
+            moves(&hand, 1, THREE, &rules, false));
+        assert_eq!(vec![None, M(2, FIVE), M(2, FOUR), M(3, FOUR)],
+            moves(&hand, 2, THREE, &rules, false));
+    }
+
+    #[test]
+    fn test_moves_plain_joker() {
+        let rules = RuleSet {joker_beats_any_count: false,
+                             .. RuleSet::default()};
+        let hand = deal(&[FOUR, FOUR, JOKER, JOKER]);
+        assert_eq!(vec![None, M(2, JOKER), M(2, FOUR)],
+            moves(&hand, 2, THREE, &rules, false));
+        assert_eq!(vec![M(1, FOUR), M(2, FOUR), M(1, JOKER), M(2, JOKER)],
+            all_moves(&hand, &rules));
+    }
+
+    #[test]
+    fn test_pass_does_not_lock_out() {
+        let rules = RuleSet {pass_locks_out: false, .. RuleSet::default()};
+        let mut state = State::new(
+            3, deal(&[FOUR, FIVE, SIX, SEVEN, THREE, KING]), rules);
+        // Hands: 0 = [THREE, KING], 1 = [SIX, SEVEN], 2 = [FOUR, FIVE].
+        state.apply(Some(Set(1, THREE))).unwrap();
+        state.apply(None).unwrap();
+        state.apply(Some(Set(1, FOUR))).unwrap();
+        state.apply(None).unwrap();
+        assert_eq!(1, state.current_player());
+        assert!(state.moves().contains(&Some(Set(1, SIX))));
+        state.apply(Some(Set(1, SIX))).unwrap();
+        state.apply(None).unwrap();
+        state.apply(None).unwrap();
+        assert_eq!(1, state.current_player());
+        assert_eq!(None, *state.top_card());
+    }
+
+    #[test]
+    fn test_end_on_first_finisher() {
+        let rules = RuleSet {end_on_first_finisher: true,
+                             .. RuleSet::default()};
+        let mut state = State::new(
+            3, deal(&[FOUR, FIVE, SIX, SEVEN, THREE, KING]), rules);
+        state.apply(Some(Set(1, THREE))).unwrap();
+        state.apply(None).unwrap();
+        state.apply(Some(Set(1, FOUR))).unwrap();
+        state.apply(Some(Set(1, KING))).unwrap();
+        assert!(state.is_terminal());
+        assert_eq!(&[0, 2, 1], state.finishing_order());
+        assert!(state.moves().is_empty());
+    }
+
+    #[test]
+    fn test_moves_revolution() {
+        let rules = RuleSet {revolution: true, .. RuleSet::default()};
+        let hand = deal(&[FOUR, SIX, NINE, JOKER]);
+        assert_eq!(vec![None, M(1, JOKER), M(1, SIX), M(1, FOUR)],
+            moves(&hand, 1, SEVEN, &rules, true));
+        assert_eq!(vec![None, M(1, JOKER), M(1, NINE)],
+            moves(&hand, 1, SEVEN, &rules, false));
+    }
+
+    #[test]
+    fn test_revolution_lasts_the_round() {
+        let rules = RuleSet {revolution: true, .. RuleSet::default()};
+        let mut state = State::new(2, deal(&[
+            THREE, FOUR, SIX, SEVEN, ACE, ACE,
+            FIVE, FIVE, FIVE, FIVE, QUEEN, KING]), rules);
+        state.apply(Some(Set(4, FIVE))).unwrap();
+        assert!(state.revolution());
+        state.apply(None).unwrap();
+        assert_eq!(None, *state.top_card());
+        state.apply(Some(Set(1, KING))).unwrap();
+        let moves = state.moves();
+        assert!(moves.contains(&Some(Set(1, SEVEN))));
+        assert!(!moves.contains(&Some(Set(1, ACE))));
+        assert!(state.to_partial_state().revolution());
+    }
+
+    #[test]
+    fn test_only_joker_beats_joker() {
+        let rules = RuleSet {revolution: true, .. RuleSet::default()};
+        let hand = deal(&[THREE, SEVEN, TWO, JOKER]);
+        for &revolution in [false, true].iter() {
+            assert_eq!(vec![None], moves(&hand, 1, JOKER, &rules, revolution));
+            for &rank in [THREE, SEVEN, TWO].iter() {
+                assert!(!super::beats(rank, JOKER, revolution, false));
+                assert!(super::beats(JOKER, rank, revolution, false));
+            }
+        }
+
+        // Hands: 0 = [FIVE x 4, SIX, JOKER], 1 = [THREE, FOUR, SEVEN, ...].
+        let deck = deal(&[THREE, FOUR, SEVEN, TWO, ACE, KING,
+                          FIVE, FIVE, FIVE, FIVE, SIX, JOKER]);
+        for &revolution in [false, true].iter() {
+            let mut state = State::new(2, deck.clone(), rules);
+            if revolution {
+                state.apply(M(4, FIVE)).unwrap();
+                state.apply(None).unwrap();
+                assert!(state.revolution());
+            }
+            state.apply(M(1, JOKER)).unwrap();
+            assert_eq!(vec![None], state.moves());
+            for &rank in [THREE, TWO].iter() {
+                assert_eq!(Err(ScumError::RankTooLow(Set(1, rank))),
+                           state.apply(M(1, rank)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stop_card() {
+        let rules = RuleSet {stop_card: Some(EIGHT), .. RuleSet::default()};
+        let mut state = State::new(3, deal(&[
+            FOUR, FIVE, ACE, SIX, SEVEN, QUEEN, THREE, EIGHT, EIGHT]), rules);
+        state.apply(Some(Set(1, THREE))).unwrap();
+        state.apply(Some(Set(1, SIX))).unwrap();
+        state.apply(None).unwrap();
+        state.apply(Some(Set(1, EIGHT))).unwrap();
+        assert_eq!(None, *state.top_card());
+        assert_eq!(0, state.current_player());
+        // Burning the pile with the last card passes the lead on.
+        state.apply(Some(Set(1, EIGHT))).unwrap();
+        assert_eq!(None, *state.top_card());
+        assert_eq!(1, state.current_player());
+        assert_eq!(&[0], state.finishing_order());
+    }
+
+    #[test]
+    fn test_stop_card_random_games() {
+        let mut rng = weak_rng();
+        let rules = RuleSet {stop_card: Some(TWO), .. RuleSet::default()};
+        for _ in 0..20 {
+            let mut deck = DECK.to_vec();
+            rng.shuffle(&mut deck[..]);
+            let mut state = State::new(4, deck, rules);
+            let first = state.moves()[0];
+            state.apply(first).unwrap();
+            let partial = state.to_partial_state();
+            let mut real = State::realisation_from(&partial, &mut rng).unwrap();
+            play_randomly(&mut real, &mut rng);
+            assert_eq!(4, real.finishing_order().len());
+        }
+    }
+
+    #[test]
+    fn test_moves_equal() {
+        let rules = RuleSet {play_equal: true, .. RuleSet::default()};
+        let hand = deal(&[FOUR, FIVE, FIVE, SIX, JOKER]);
+        assert_eq!(vec![None, M(1, JOKER), M(1, SIX), M(1, FIVE)],
+            moves(&hand, 1, FIVE, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER), M(1, FIVE), M(1, FOUR)],
+            moves(&hand, 1, FIVE, &rules, true));
+    }
+
+    #[test]
+    fn test_skip_on_equal() {
+        let rules = RuleSet {play_equal: true, skip_on_equal: true,
+                             .. RuleSet::default()};
+        let mut state = State::new(3, deal(&[
+            FOUR, SEVEN, ACE, FIVE, SIX, QUEEN, THREE, FIVE, KING]), rules);
+        // Hands: 0 = [THREE, FIVE, KING], 1 = [FIVE, SIX, QUEEN].
+        state.apply(Some(Set(1, FIVE))).unwrap();
+        assert!(state.moves().contains(&Some(Set(1, FIVE))));
+        state.apply(Some(Set(1, FIVE))).unwrap();
+        // Player 2 is skipped.
+        assert_eq!(0, state.current_player());
+        state.apply(None).unwrap();
+        assert_eq!(1, state.current_player());
+    }
+
+    #[test]
+    fn test_card() {
+        let card = Card::new(QUEEN, Suit::Hearts);
+        assert_eq!(QUEEN, card.rank());
+        assert_eq!(Suit::Hearts, card.suit());
+        assert!(card < Card::new(KING, Suit::Clubs));
+        for rank in THREE..TWO + 1 {
+            let suits: Vec<Suit> = DECK.iter()
+                .filter(|c| c.rank() == rank).map(|c| c.suit()).collect();
+            assert_eq!(vec![Suit::Clubs, Suit::Diamonds,
+                            Suit::Hearts, Suit::Spades], suits);
+        }
+        assert_eq!(2, DECK.iter().filter(|c| c.rank() == JOKER).count());
+    }
+
+    #[test]
+    fn test_runs() {
+        let rules = RuleSet {runs: true, .. RuleSet::default()};
+        let hand = deal(&[THREE, FIVE, SIX, SIX, SEVEN, EIGHT, TWO, JOKER]);
+        let moves = all_moves(&hand, &rules);
+        assert!(moves.contains(&Some(Run(3, FIVE))));
+        assert!(moves.contains(&Some(Run(4, FIVE))));
+        assert!(moves.contains(&Some(Run(3, SIX))));
+        assert!(!moves.contains(&Some(Run(3, THREE))));
+        assert_eq!(vec![None, M(1, JOKER), Some(Run(3, SIX)),
+                        Some(Run(3, FIVE))],
+            run_moves(&hand, 3, FOUR, &rules, false));
+        assert_eq!(vec![None, M(1, JOKER), Some(Run(4, FIVE))],
+            run_moves(&hand, 4, THREE, &rules, false));
+    }
+
+    #[test]
+    fn test_apply_run() {
+        let rules = RuleSet {runs: true, .. RuleSet::default()};
+        let mut state = State::new(2, deal(&[
+            SIX, SEVEN, EIGHT, NINE, KING,
+            THREE, FOUR, FIVE, QUEEN, ACE]), rules);
+        state.apply(Some(Run(3, THREE))).unwrap();
+        assert_eq!(vec![None, Some(Run(3, SEVEN)), Some(Run(3, SIX))],
+            state.moves());
+        state.apply(Some(Run(3, SIX))).unwrap();
+        assert_eq!(vec![None], state.moves());
+        state.apply(None).unwrap();
+        assert_eq!(1, state.current_player());
+        assert_eq!(2, state.hand(0).len());
+        assert_eq!(2, state.hand(1).len());
+    }
+
+    #[test]
+    fn test_wild_jokers() {
+        let rules = RuleSet {wild_jokers: true, .. RuleSet::default()};
+        let hand = deal(&[FIVE, NINE, NINE, JOKER]);
+        assert_eq!(vec![M(1, FIVE), Some(Wild(2, FIVE, 1)),
+                        M(1, NINE), M(2, NINE), Some(Wild(3, NINE, 1)),
+                        M(1, JOKER)],
+            all_moves(&hand, &rules));
+        assert_eq!(vec![None, M(1, JOKER), Some(Wild(3, NINE, 1))],
+            moves(&hand, 3, EIGHT, &rules, false));
+    }
+
+    #[test]
+    fn test_apply_wild() {
+        let rules = RuleSet {wild_jokers: true, .. RuleSet::default()};
+        let mut state = State::new(2, deal(&[
+            TEN, TEN, TEN, THREE, FIVE, NINE, NINE, JOKER]), rules);
+        state.apply(Some(Wild(3, NINE, 1))).unwrap();
+        assert_eq!(deal(&[NINE, NINE, JOKER]), state.discard);
+        assert_eq!(deal(&[FIVE]), *state.hand(0));
+        assert_eq!(vec![None, M(3, TEN)], state.moves());
+        state.apply(Some(Set(3, TEN))).unwrap();
+        assert_eq!(6, state.discard.len());
+    }
+
+    #[test]
+    fn test_illegal_moves() {
+        let rules = RuleSet {wild_jokers: true, .. RuleSet::default()};
+        let mut state = State::new(2, deal(&[
+            TEN, TEN, TEN, THREE, FIVE, NINE, NINE, JOKER]), rules);
+        assert_eq!(Err(ScumError::IllegalMove(None)), state.apply(None));
+        assert_eq!(Err(ScumError::CardNotInHand(ACE)),
+                   state.apply(M(1, ACE)));
+        assert_eq!(Err(ScumError::CardNotInHand(NINE)),
+                   state.apply(M(3, NINE)));
+        assert_eq!(Err(ScumError::IllegalMove(Some(Run(3, THREE)))),
+                   state.apply(Some(Run(3, THREE))));
+        assert_eq!(Err(ScumError::IllegalCount(Set(2, JOKER))),
+                   state.apply(M(2, JOKER)));
+        assert_eq!(Err(ScumError::IllegalMove(Some(Wild(2, NINE, 0)))),
+                   state.apply(Some(Wild(2, NINE, 0))));
+        // Nothing happened.
+        assert_eq!(0, state.current_player());
+        assert!(state.discard.is_empty());
+
+        state.apply(M(2, NINE)).unwrap();
+        assert_eq!(Err(ScumError::IllegalCount(Set(1, TEN))),
+                   state.apply(M(1, TEN)));
+        assert_eq!(Err(ScumError::RankTooLow(Set(2, THREE))),
+                   state.apply(M(2, THREE)));
+        for &muve in [M(2, TEN), None, M(1, TEN), M(1, JOKER), None,
+                      M(1, FIVE)].iter() {
+            state.apply(muve).unwrap();
+        }
+        assert!(state.is_terminal());
+        assert_eq!(Err(ScumError::GameOver), state.apply(None));
+        assert_eq!("card not in hand: A",
+                   format!("{}", ScumError::CardNotInHand(ACE)));
+    }
+
+    #[test]
+    fn test_realisation_inconsistent() {
+        let mut rng = weak_rng();
+        let state = State::new(4, DECK.to_vec(), RuleSet::default());
+        let mut partial = state.to_partial_state();
+        partial.hand_sizes[1] += 1;
+        assert!(State::realisation_from(&partial, &mut rng).is_err());
+        let mut partial = state.to_partial_state();
+        partial.cards.pop();
+        assert!(State::realisation_from(&partial, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_deck_spec() {
+        let single = DeckSpec::Standard {decks: 1, jokers: true};
+        assert_eq!(DECK.to_vec(), single.cards());
+        let double = DeckSpec::Standard {decks: 2, jokers: false};
+        assert_eq!(104, double.cards().len());
+        let aces = double.cards().iter().filter(|c| c.rank() == ACE).count();
+        assert_eq!(8, aces);
+        assert_eq!(single, DeckSpec::for_players(7));
+        assert_eq!(DeckSpec::Standard {decks: 2, jokers: true},
+                   DeckSpec::for_players(8));
+    }
+
+    #[test]
+    fn test_multiple_decks() {
+        let mut rng = weak_rng();
+        let mut deck = DeckSpec::for_players(12).cards();
+        rng.shuffle(&mut deck[..]);
+        let mut state = State::new(12, deck, RuleSet::default());
+        assert_eq!(108, state.cards().len());
+        for _ in 0..30 {
+            let first = state.moves()[0];
+            state.apply(first).unwrap();
+        }
+        let partial = state.to_partial_state();
+        let mut real = State::realisation_from(&partial, &mut rng).unwrap();
+        assert_eq!(state.cards(), real.cards());
+        play_randomly(&mut real, &mut rng);
+        assert_eq!(12, real.finishing_order().len());
+    }
+
+    #[test]
+    fn test_compact_state() {
+        let rules = RuleSet {runs: true, wild_jokers: true,
+                             .. RuleSet::default()};
+        let mut compact: Option<CompactState> = None;
+        // A bigger table to copy into.
+        let mut scratch = State::new(6, DECK.to_vec(), rules).compact();
+        let state = random_game(rules, 4, &mut weak_rng(), |state, muve, _| {
+            if compact.is_none() {
+                compact = Some(state.compact());
+            }
+            let compact = compact.as_mut().unwrap();
+            assert_eq!(state.moves(), compact.moves());
+            for p in 0..4 {
+                assert_eq!(counts(state.hand(p)), *compact.hand(p));
+            }
+            scratch.copy_from(compact);
+            assert_eq!(format!("{:?}", compact), format!("{:?}", scratch));
+            assert_eq!(compact.zobrist(), scratch.zobrist());
+            compact.apply(muve).unwrap();
+        });
+        let compact = compact.unwrap();
+        assert!(compact.is_terminal());
+        assert_eq!(state.finishing_order(), compact.finishing_order());
+        let mut copy = state.compact();
+        assert_eq!(Err(ScumError::GameOver), copy.apply(None));
+    }
+
+    #[test]
+    fn test_undo() {
+        let rules = RuleSet {pass_locks_out: false, stop_card: Some(EIGHT),
+                             skip_on_equal: true, play_equal: true,
+                             revolution: true, .. RuleSet::default()};
+        let mut first = None;
+        random_game(rules, 4, &mut weak_rng(), |state, muve, _| {
+            if first.is_none() {
+                first = Some(state.clone());
+            }
+            let before = format!("{:?}", state);
+            let undo = state.apply(muve).unwrap();
+            state.undo(undo);
+            assert_eq!(before, format!("{:?}", state));
+        });
+
+        let state = first.unwrap();
+        let mut compact = state.compact();
+        let first = compact.moves()[0];
+        let undo = compact.apply(first).unwrap();
+        compact.undo(undo);
+        assert_eq!(format!("{:?}", state.compact()), format!("{:?}", compact));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut state = State::new(3, deal(&[
+            THREE, FOUR, FIVE, SIX, SEVEN, EIGHT]), RuleSet::default());
+        let turn = |player, muve| Turn {player: player, muve: muve};
+        state.apply(Some(Set(1, SEVEN))).unwrap();
+        state.apply(None).unwrap();
+        state.apply(None).unwrap();
+        let undo = state.apply(Some(Set(1, EIGHT))).unwrap();
+        let history = vec![
+            vec![turn(0, Some(Set(1, SEVEN))), turn(1, None), turn(2, None)],
+            vec![turn(0, Some(Set(1, EIGHT)))]];
+        assert_eq!(&history[..], state.history());
+        assert_eq!(&history[..], state.partial_state_for(2).history());
+
+        state.undo(undo);
+        assert_eq!(&history[..1], state.history());
+        let realisation = State::realisation_from(
+            &state.partial_state_for(1), &mut weak_rng()).unwrap();
+        assert_eq!(&history[..1], realisation.history());
+    }
+
+    #[test]
+    fn test_history_of_parsed_state() {
+        // Read in the middle of a trick, with nothing of it in the history.
+        let mut state: State = "jcp 8c/5c6c/3c4c 1x7@0 0 - 1,2,0 - 7c -"
+            .parse().unwrap();
+        let before = format!("{:?}", state);
+        let undo = state.apply(None).unwrap();
+        assert_eq!(&[vec![Turn {player: 1, muve: None}]], state.history());
+        state.undo(undo);
+        assert!(state.history().is_empty());
+        assert_eq!(before, format!("{:?}", state));
+
+        let partial: PartialState = state.partial_state_for(1).to_string()
+            .parse().unwrap();
+        let mut real = State::realisation_from(&partial, &mut weak_rng())
+            .unwrap();
+        real.apply(None).unwrap();
+        real.apply(None).unwrap();
+        let lead = real.moves()[0];
+        real.apply(lead).unwrap();
+        assert_eq!(&[vec![Turn {player: 1, muve: None},
+                          Turn {player: 2, muve: None}],
+                     vec![Turn {player: 0, muve: lead}]], real.history());
+    }
+
+    #[test]
+    fn test_zobrist() {
+        // What apply keeps up to date, worked out from scratch.
+        let from_scratch = |core: &CompactState| core.full_hash()
+            ^ super::zobrist::queue(core.next_player.to_vec().into_iter());
+
+        let mut state = State::new(4, DECK.to_vec(), RuleSet::default());
+        let before = state.zobrist();
+        let gift = state.hand(0)[..2].to_vec();
+        state.exchange(0, 3, gift).unwrap();
+        assert_eq!(from_scratch(&state.core), state.zobrist());
+        assert!(before != state.zobrist());
+        let partial = state.partial_state_for(0);
+        let real = State::realisation_from(&partial, &mut weak_rng()).unwrap();
+        assert_eq!(partial.zobrist(), real.partial_state_for(0).zobrist());
+
+        let mut seen = vec![];
+        let last = random_game(RuleSet::default(), 4, &mut weak_rng(),
+                               |state, muve, rng| {
+            assert_eq!(from_scratch(&state.core), state.zobrist());
+            let before = state.zobrist();
+            assert!(!seen.contains(&before));
+            seen.push(before);
+            let undo = state.apply(muve).unwrap();
+            assert_eq!(from_scratch(&state.core), state.zobrist());
+            state.undo(undo);
+            assert_eq!(before, state.zobrist());
+
+            // Whatever the others hold, the player sees the same thing.
+            let partial = state.partial_state_for(0);
+            let real = State::realisation_from(&partial, rng).unwrap();
+            assert_eq!(partial.zobrist(),
+                       real.partial_state_for(0).zobrist());
+        });
+        assert!(!seen.contains(&last.zobrist()));
     }
 
     #[test]
     fn test_num_players() {
         for i in 1..55 {
-            assert_eq!(i, State::new(i, DECK.to_vec()).num_players());
+            let state = State::new(i, DECK.to_vec(), RuleSet::default());
+            assert_eq!(i, state.num_players());
         }
     }
 
     #[test]
     fn test_new() {
-        let state = State::new(5, DECK.to_vec());
+        let state = State::new(5, DECK.to_vec(), RuleSet::default());
         assert_eq!(10, state.hands[0].len());
         assert_eq!(11, state.hands[1].len());
         assert_eq!(11, state.hands[2].len());
@@ -575,82 +2301,152 @@ mod tests {
 
     #[test]
     fn smoke_test() {
-        let mut state = State::new(4, DECK.to_vec());
+        let mut state = State::new(4, DECK.to_vec(), RuleSet::default());
         while !state.is_terminal() {
             let action = state.moves().pop().expect(
                 &(format!("State didn't produce moves: {:?}", state)));
-            state.apply(action);
+            state.apply(action).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_realisation_keeps_gifts() {
+        let mut rng = weak_rng();
+        let mut state = State::new(4, DECK.to_vec(), RuleSet::default());
+        let gift = state.hand(0)[..2].to_vec();
+        state.exchange(0, 1, gift.clone()).unwrap();
+        let partial = state.to_partial_state();
+        for _ in 0..20 {
+            let real = State::realisation_from(&partial, &mut rng).unwrap();
+            for card in gift.iter() {
+                assert!(real.hand(1).contains(card));
+            }
+        }
+    }
+
+    #[test]
+    fn test_realisation_keeps_gift_copied_by_other_deck() {
+        let mut rng = weak_rng();
+        // The first and fifth FIVE are both clubs, as with two decks.
+        let mut state = State::new(3, deal(&[
+            FIVE, SIX, SEVEN, FIVE, FIVE, FIVE, FIVE, THREE, FOUR]),
+            RuleSet::default());
+        // Hands: 0 = [THREE, FOUR, FIVE], 1 = [FIVE x 3], 2 = [FIVE, SIX, ...].
+        let five = Card::new(FIVE, Suit::Clubs);
+        state.exchange(0, 1, vec![five]).unwrap();
+        state.apply(Some(Set(1, THREE))).unwrap();
+        state.apply(None).unwrap();
+        // Player 2 plays their own club FIVE, not the one player 1 got.
+        state.apply(Some(Set(1, FIVE))).unwrap();
+        assert_eq!(&[Card::new(THREE, Suit::Clubs), five], &state.discard[..]);
+        let partial = state.partial_state_for(0);
+        for _ in 0..20 {
+            let real = State::realisation_from(&partial, &mut rng).unwrap();
+            assert!(real.hand(1).contains(&five));
+        }
+    }
+
+    #[test]
+    fn test_full_finishing_order() {
+        let mut rng = weak_rng();
+        for num_players in 2..8 {
+            let state = random_game(RuleSet::default(), num_players, &mut rng,
+                                    |_, _, _| {});
+            let mut order = state.finishing_order().to_vec();
+            assert_eq!(num_players, order.len());
+            order.sort();
+            assert_eq!((0..num_players as u8).collect::<Vec<_>>(), order);
+        }
+    }
+
+    #[test]
+    fn test_finished_player_skipped() {
+        let mut state = State::new(
+            3, deal(&[FOUR, TWO, FIVE, FIVE, KING, ACE]), RuleSet::default());
+        // Hands: 0 = [KING, ACE], 1 = [FIVE, FIVE], 2 = [FOUR, TWO].
+        state.apply(Some(Set(1, KING))).unwrap();
+        state.apply(None).unwrap();
+        state.apply(None).unwrap();
+        assert_eq!(0, state.current_player());
+        state.apply(Some(Set(1, ACE))).unwrap();
+        assert_eq!(&[0], state.finishing_order());
+        assert_eq!(Some(Set(1, ACE)), *state.top_card());
+        // Player 1 passes, and player 2 still gets to answer the ACE.
+        state.apply(None).unwrap();
+        assert_eq!(2, state.current_player());
+        assert_eq!(Some(Set(1, ACE)), *state.top_card());
+        state.apply(Some(Set(1, TWO))).unwrap();
+        assert_eq!(2, state.current_player());
+        assert_eq!(None, *state.top_card());
+        state.apply(Some(Set(1, FOUR))).unwrap();
+        assert_eq!(&[0, 2, 1], state.finishing_order());
+        assert!(state.is_terminal());
+
+        // When everybody passes on it, the lead goes to the next seat.
+        let mut state = State::new(
+            3, deal(&[FOUR, THREE, FIVE, FIVE, TWO, ACE]), RuleSet::default());
+        for &muve in [Some(Set(1, ACE)), None, None, Some(Set(1, TWO)), None,
+                      None].iter() {
+            state.apply(muve).unwrap();
         }
+        assert_eq!(&[0], state.finishing_order());
+        assert_eq!(1, state.current_player());
+        assert_eq!(None, *state.top_card());
     }
 }
 
 #[cfg(test)]
 mod bench {
     use super::*;
-    use super::{all_moves, moves};
+    use super::{all_moves, set_moves};
+    use super::tests::deal;
 
     use rand::{Rng, weak_rng};
+    use std::default::Default;
     use test::Bencher;
 
-    #[bench]
-    fn bench_all_moves(b: &mut Bencher) {
-        let hand = vec![
+    fn hand() -> Counts {
+        counts(&deal(&[
             THREE,
             FOUR, FOUR, FOUR, FOUR,
             FIVE, FIVE, FIVE,
-            JOKER, JOKER];
-        b.iter(|| {
-            all_moves(&hand)
-        });
+            JOKER, JOKER]))
     }
 
     #[bench]
-    fn bench_moves_1(b: &mut Bencher) {
-        let hand = vec![
-            THREE,
-            FOUR, FOUR, FOUR, FOUR,
-            FIVE, FIVE, FIVE,
-            JOKER, JOKER];
+    fn bench_all_moves(b: &mut Bencher) {
+        let rules = RuleSet::default();
+        let hand = hand();
+        let mut moves = Vec::with_capacity(32);
         b.iter(|| {
-            moves(&hand, 1, THREE)
+            moves.clear();
+            all_moves(&hand, &rules, &mut moves);
+            moves.len()
         });
     }
 
-    #[bench]
-    fn bench_moves_2(b: &mut Bencher) {
-        let hand = vec![
-            THREE,
-            FOUR, FOUR, FOUR, FOUR,
-            FIVE, FIVE, FIVE,
-            JOKER, JOKER];
+    fn bench_set_moves(b: &mut Bencher, count: u8) {
+        let rules = RuleSet::default();
+        let hand = hand();
+        let mut moves = Vec::with_capacity(32);
         b.iter(|| {
-            moves(&hand, 2, THREE)
+            moves.clear();
+            set_moves(&hand, count, THREE, &rules, false, &mut moves);
+            moves.len()
         });
     }
 
     #[bench]
-    fn bench_moves_3(b: &mut Bencher) {
-        let hand = vec![
-            THREE,
-            FOUR, FOUR, FOUR, FOUR,
-            FIVE, FIVE, FIVE,
-            JOKER, JOKER];
-        b.iter(|| {
-            moves(&hand, 3, THREE)
-        });
-    }
+    fn bench_moves_1(b: &mut Bencher) { bench_set_moves(b, 1) }
 
     #[bench]
-    fn bench_moves_4(b: &mut Bencher) {
-        let hand = vec![
-            THREE,
-            FOUR, FOUR, FOUR, FOUR,
-            FIVE, FIVE, FIVE,
-            JOKER, JOKER];
-        b.iter(|| {
-            moves(&hand, 4, THREE)
-        });
-    }
+    fn bench_moves_2(b: &mut Bencher) { bench_set_moves(b, 2) }
+
+    #[bench]
+    fn bench_moves_3(b: &mut Bencher) { bench_set_moves(b, 3) }
+
+    #[bench]
+    fn bench_moves_4(b: &mut Bencher) { bench_set_moves(b, 4) }
 
     #[bench]
     fn bench_random_game(b: &mut Bencher) {
@@ -658,21 +2454,36 @@ mod bench {
         b.iter(|| {
             let mut deck = DECK.to_vec();
             rng.shuffle(&mut deck[..]);
-            let mut state = State::new(5, deck);
+            let mut state = State::new(5, deck, RuleSet::default());
             play_randomly(&mut state, &mut rng);
             state.winner()
         });
     }
 
+    // The same, played out the way the searches do.
+    #[bench]
+    fn bench_random_game_compact(b: &mut Bencher) {
+        let mut rng = weak_rng();
+        let mut scratch = State::new(5, DECK.to_vec(), RuleSet::default())
+            .compact();
+        b.iter(|| {
+            let mut deck = DECK.to_vec();
+            rng.shuffle(&mut deck[..]);
+            let state = State::new(5, deck, RuleSet::default()).compact();
+            rollout(&state, &mut scratch, &mut rng)
+        });
+    }
+
     #[bench]
     fn bench_uct(b: &mut Bencher) {
         let mut rng = weak_rng();
         let mut deck = DECK.to_vec();
         rng.shuffle(&mut deck[..]);
-        let state = State::new(5, deck);
+        let mut state = State::new(5, deck, RuleSet::default()).compact();
+        let mut scratch = state;
         let mut root = Node::new(state.current_player(), state.moves());
         b.iter(|| {
-            root.uct(&mut state.clone(), &mut rng);
+            root.uct(&mut state, &mut scratch, &mut rng);
             root.plays
         });
     }
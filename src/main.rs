@@ -3,28 +3,77 @@
 extern crate scum;
 extern crate rand;
 
-use rand::{Rng, weak_rng};
-use scum::{Player, CheatingUCT, FairUCT, ConsolePlayer, DECK, State};
+use std::default::Default;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
 
+use scum::{Player, CheatingUCT, FairUCT, ConsolePlayer, Match, MatchEnd,
+           RuleSet, Scoring};
+
+const TARGET_SCORE: i32 = 10;
+
+// The players, with the names they get in the records.
+fn console() -> (String, Box<Player>) {
+    ("Console".to_string(), box ConsolePlayer)
+}
+
+fn cheating_uct(iters: usize) -> (String, Box<Player>) {
+    (format!("CheatingUCT {}", iters), box CheatingUCT::new(iters))
+}
+
+fn fair_uct(reals: usize, iters: usize) -> (String, Box<Player>) {
+    (format!("FairUCT {}x{}", reals, iters), box FairUCT::new(reals, iters))
+}
+
+// Plays a match against the AIs. The seed is printed first; giving it again
+// deals the same cards and has the AIs play the same way. Every round is
+// added to the record file if one is given:
+//
+//     scum [--seed N] [record file]
 fn main() {
-    let mut rng = weak_rng();
-    let mut deck = DECK.to_vec();
+    let mut seed = None;
+    let mut record_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            seed = Some(args.next().and_then(|s| s.parse().ok())
+                        .expect("--seed needs a number"));
+        } else {
+            record_path = Some(arg);
+        }
+    }
+    let seed = seed.unwrap_or_else(|| rand::random());
+    println!("SEED {}", seed);
+    let table = vec![console(), cheating_uct(100000), fair_uct(20, 1000),
+                     fair_uct(20, 1000), fair_uct(20, 10000)];
+    let mut names = vec![];
     let mut players: Vec<Box<Player>> = vec![];
-    players.push(box ConsolePlayer);
-    players.push(box CheatingUCT::new(100000));
-    players.push(box FairUCT::new(20, 1000));
-    players.push(box FairUCT::new(20, 1000));
-    players.push(box FairUCT::new(20, 10000));
-    rng.shuffle(&mut deck[..]);
-    let mut state = State::new(players.len(), deck);
-    while !state.is_terminal() {
-        println!("{:?}", state);
-        let player_index = state.current_player() as usize;
-        println!("It's #{}'s turn:\n\t{:?}", player_index, state.top_card());
-        let player = &mut players[player_index];
-        let move_ = player.choose_move(state.clone());
-        println!("#{} played {:?}", player_index, move_);
-        state.apply(move_);
+    for (name, player) in table.into_iter() {
+        names.push(name);
+        players.push(player);
+    }
+    let num_players = players.len();
+    let scoring = Scoring::linear(num_players, MatchEnd::Points(TARGET_SCORE));
+    let mut game = Match::new(num_players, RuleSet::default(), scoring, seed);
+    while !game.is_over() {
+        println!("\n\nROUND {}", game.rounds().len() + 1);
+        // The console player keeps asking until it gets a legal move, so an
+        // illegal move or gift is a bug in an AI, and playing the round
+        // again would only get the same one back.
+        let mut record = match game.play_round(&mut players[..]) {
+            Ok(record) => record,
+            Err(err) => panic!("an AI broke the rules: {}", err),
+        };
+        record.players = names.clone();
+        println!("\n{}", record);
+        if let Some(ref path) = record_path {
+            let mut file = OpenOptions::new().create(true).append(true)
+                .open(path).ok().expect("couldn't open the record file");
+            write!(&mut file, "{}\n", record).ok()
+                .expect("couldn't write the record");
+        }
+        println!("{}", game.standings());
     }
-    println!("\n\nWINNER: {}", state.winner());
+    println!("\n\nWINNER: {}", game.winner());
 }
@@ -0,0 +1,243 @@
+use rand::Rng;
+use std::cmp::min;
+
+use {DeckSpec, GameRecord, Player, RuleSet, ScumError, State};
+use scoring::{Scoring, Standings};
+use seed::{player_seed, round_seed, seeded_rng};
+
+// Several rounds of Scum played by the same table. Before every round but the
+// first, the players swap cards according to the previous finishing order.
+// Every round is dealt from a seed worked out from the match's, and the
+// players are seeded from it too, so a match seed plays the match again.
+pub struct Match {
+    num_players: usize,
+    rules: RuleSet,
+    deck: DeckSpec,
+    exchanges: Vec<usize>,
+    rounds: Vec<Vec<u8>>,
+    scoring: Scoring,
+    standings: Standings,
+    seed: u64,
+}
+
+impl Match {
+    // President and Scum swap two cards, the Vice-President and Vice-Scum one.
+    // Large tables play with more than one deck.
+    pub fn new(num_players: usize, rules: RuleSet, scoring: Scoring,
+               seed: u64) -> Match {
+        let deck = DeckSpec::for_players(num_players);
+        let exchanges = [2, 1].iter().cloned().take(num_players / 2).collect();
+        Match::with_exchanges(num_players, rules, deck, exchanges, scoring,
+                              seed)
+    }
+
+    // `exchanges[i]` is the number of cards swapped between the players that
+    // finished `i`th and `i`th from last.
+    pub fn with_exchanges(num_players: usize, rules: RuleSet, deck: DeckSpec,
+                          exchanges: Vec<usize>, scoring: Scoring, seed: u64)
+                          -> Match {
+        assert!(exchanges.len() <= num_players / 2);
+        Match {num_players: num_players, rules: rules, deck: deck,
+               exchanges: exchanges, rounds: vec![], scoring: scoring,
+               standings: Standings::new(num_players), seed: seed}
+    }
+
+    pub fn num_players(&self) -> usize { self.num_players }
+    pub fn rules(&self) -> &RuleSet { &self.rules }
+    pub fn deck(&self) -> &DeckSpec { &self.deck }
+    pub fn rounds<'a>(&'a self) -> &'a [Vec<u8>] { &self.rounds[..] }
+    pub fn standings<'a>(&'a self) -> &'a Standings { &self.standings }
+    pub fn is_over(&self) -> bool { self.scoring.is_over(&self.standings) }
+    pub fn winner(&self) -> u8 { self.standings.leader() }
+    pub fn seed(&self) -> u64 { self.seed }
+
+    // Seeds the players, deals a new round and does the card exchange. Fails
+    // if a player gives back the wrong number of cards or cards they don't
+    // hold.
+    pub fn start_round(&self, players: &mut [Box<Player + 'static>])
+                       -> Result<State, ScumError> {
+        assert_eq!(self.num_players, players.len());
+        let round = round_seed(self.seed, self.rounds.len());
+        for (seat, player) in players.iter_mut().enumerate() {
+            player.seed(player_seed(round, seat as u8));
+        }
+        let mut deck = self.deck.cards();
+        seeded_rng(round).shuffle(&mut deck[..]);
+        let mut state = State::new(self.num_players, deck, self.rules);
+        let order = match self.rounds.last() {
+            Some(order) => order,
+            None => return Ok(state),
+        };
+        for (i, &count) in self.exchanges.iter().enumerate() {
+            let high = order[i];
+            let low = order[self.num_players - 1 - i];
+            // Small decks can leave a hand with fewer cards than are due.
+            let count = min(count, state.hand(low).len());
+            // The low player has to give up their best cards.
+            let tribute = {
+                let hand = state.hand(low);
+                hand[hand.len() - count..].to_vec()
+            };
+            try!(state.exchange(low, high, tribute));
+            let gift = players[high as usize]
+                .choose_gift(state.clone(), high, count);
+            if gift.len() != count {
+                return Err(ScumError::WrongGiftCount(count));
+            }
+            try!(state.exchange(high, low, gift));
+        }
+        Ok(state)
+    }
+
+    pub fn finish_round(&mut self, state: &State) {
+        assert!(state.is_terminal());
+        self.standings.add_round(&self.scoring, state.finishing_order());
+        self.rounds.push(state.finishing_order().to_vec());
+    }
+
+    // Plays a round and returns its record, which has the match seed and
+    // the round but leaves the player names to the caller. Fails if a player
+    // makes an illegal move or gift, leaving the round unscored.
+    pub fn play_round(&mut self, players: &mut [Box<Player + 'static>])
+                      -> Result<GameRecord, ScumError> {
+        let mut state = try!(self.start_round(players));
+        let mut record = GameRecord::new(vec![], Some(self.seed), &state);
+        record.round = Some(self.rounds.len() + 1);
+        while !state.is_terminal() {
+            let player = state.current_player() as usize;
+            let move_ = players[player].choose_move(state.clone());
+            try!(state.apply(move_));
+            record.push(move_);
+        }
+        self.finish_round(&state);
+        Ok(record)
+    }
+
+    pub fn play(&mut self, players: &mut [Box<Player + 'static>])
+                -> Result<(), ScumError> {
+        while !self.is_over() {
+            try!(self.play_round(players));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Card, CheatingUCT, DeckSpec, Move, Player, RuleSet, ScumError,
+         State, Suit};
+    use {THREE, FOUR, FIVE, SIX, JOKER};
+    use scoring::{MatchEnd, Scoring};
+
+    use std::default::Default;
+
+    fn cheating_players(n: usize) -> Vec<Box<Player>> {
+        (0..n).map(|_| Box::new(CheatingUCT::new(10)) as Box<Player>).collect()
+    }
+
+    #[test]
+    fn test_exchange() {
+        let mut players = cheating_players(4);
+        let scoring = Scoring::linear(4, MatchEnd::Rounds(1));
+        let mut game = Match::new(4, RuleSet::default(), scoring, 7);
+        game.play_round(&mut players[..]).unwrap();
+        let order = game.rounds()[0].clone();
+        let state = game.start_round(&mut players[..]).unwrap();
+        let president = state.partial_state_for(order[0]);
+        let scum = state.partial_state_for(order[3]);
+        assert_eq!(2, president.received()[0].cards.len());
+        assert_eq!(president.received(), scum.gave());
+        assert_eq!(president.gave(), scum.received());
+        assert_eq!(1, state.partial_state_for(order[1]).gave().len());
+        for p in 0..4 {
+            assert!(state.hand(p).len() >= 13);
+        }
+    }
+
+    #[test]
+    fn test_exchange_bigger_than_hand() {
+        let mut players = cheating_players(4);
+        let scoring = Scoring::linear(4, MatchEnd::Rounds(2));
+        let deck = DeckSpec::Custom(vec![THREE, FOUR, FIVE, SIX]);
+        let mut game = Match::with_exchanges(4, RuleSet::default(), deck,
+                                             vec![2], scoring, 7);
+        game.play_round(&mut players[..]).unwrap();
+        let order = game.rounds()[0].clone();
+        let state = game.start_round(&mut players[..]).unwrap();
+        let scum = state.partial_state_for(order[3]);
+        assert_eq!(1, scum.gave()[0].cards.len());
+        assert_eq!(1, scum.received()[0].cards.len());
+    }
+
+    // Plays the first move it is offered and gives back its cards, whether
+    // it holds them or not.
+    struct BadGift(Vec<Card>);
+
+    impl Player for BadGift {
+        fn choose_move(&mut self, s: State) -> Move { s.moves()[0] }
+
+        fn choose_gift(&mut self, _: State, _: u8, _: usize) -> Vec<Card> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_bad_gift() {
+        let deck = DeckSpec::Custom(vec![THREE, FOUR, FIVE, SIX,
+                                         THREE, FOUR, FIVE, SIX]);
+        let joker = Card::new(JOKER, Suit::Clubs);
+        for &(ref gift, ref err) in [
+                (vec![], ScumError::WrongGiftCount(1)),
+                (vec![joker], ScumError::CardNotInHand(JOKER))].iter() {
+            let mut players: Vec<Box<Player>> = vec![];
+            for _ in 0..4 {
+                players.push(Box::new(BadGift(gift.clone())));
+            }
+            let scoring = Scoring::linear(4, MatchEnd::Rounds(2));
+            let mut game = Match::with_exchanges(
+                4, RuleSet::default(), deck.clone(), vec![1], scoring, 7);
+            game.play_round(&mut players[..]).unwrap();
+            assert_eq!(Err(err.clone()),
+                       game.start_round(&mut players[..])
+                           .map(|_| ()));
+            assert_eq!(Err(err.clone()),
+                       game.play_round(&mut players[..])
+                           .map(|_| ()));
+            assert_eq!(1, game.rounds().len());
+        }
+    }
+
+    #[test]
+    fn test_play_to_rounds() {
+        let mut players = cheating_players(3);
+        let scoring = Scoring::linear(3, MatchEnd::Rounds(4));
+        let mut game = Match::new(3, RuleSet::default(), scoring, 7);
+        game.play(&mut players[..]).unwrap();
+        assert!(game.is_over());
+        assert_eq!(4, game.rounds().len());
+        let total = (0..3).fold(0, |t, p| t + game.standings().total(p));
+        assert_eq!(4 * 3, total);
+    }
+
+    #[test]
+    fn test_seed_replays_the_match() {
+        let play = |seed: u64| {
+            let mut players = cheating_players(3);
+            let scoring = Scoring::linear(3, MatchEnd::Rounds(3));
+            let mut game = Match::new(3, RuleSet::default(), scoring, seed);
+            let mut records = vec![];
+            while !game.is_over() {
+                records.push(game.play_round(&mut players[..]).unwrap());
+            }
+            for (record, order) in records.iter().zip(game.rounds().iter()) {
+                assert_eq!(Some(seed), record.seed);
+                let end = record.replay().unwrap().pop().unwrap();
+                assert_eq!(&order[..], end.finishing_order());
+            }
+            records.iter().map(|r| r.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(play(7), play(7));
+        assert!(play(7) != play(8));
+    }
+}
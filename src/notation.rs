@@ -0,0 +1,251 @@
+// The text form of ranks and moves, shared by the console, logs and game
+// records:
+//
+//     3 4 5 6 7 8 9 10 J Q K A 2 Jk    ranks
+//     c d h s                          suits
+//     Qh 10c Jks                       cards: rank then suit
+//     2xQ                              a pair of Queens
+//     3x9+1Jk                          three NINEs, one of them a joker
+//     5-7                              a run of FIVE, SIX and SEVEN
+//     pass
+//     #1 2xQ, #2 pass                  a trick: who played what
+use std::fmt;
+use std::str::FromStr;
+
+use {Card, Move, Play, Rank, ScumError, Suit, Turn};
+use Play::{Run, Set, Wild};
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Rank::Three => "3",
+            Rank::Four => "4",
+            Rank::Five => "5",
+            Rank::Six => "6",
+            Rank::Seven => "7",
+            Rank::Eight => "8",
+            Rank::Nine => "9",
+            Rank::Ten => "10",
+            Rank::Jack => "J",
+            Rank::Queen => "Q",
+            Rank::King => "K",
+            Rank::Ace => "A",
+            Rank::Two => "2",
+            Rank::Joker => "Jk",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Rank {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<Rank, ScumError> {
+        Ok(match s {
+            "3" => Rank::Three,
+            "4" => Rank::Four,
+            "5" => Rank::Five,
+            "6" => Rank::Six,
+            "7" => Rank::Seven,
+            "8" => Rank::Eight,
+            "9" => Rank::Nine,
+            "10" => Rank::Ten,
+            "J" | "j" => Rank::Jack,
+            "Q" | "q" => Rank::Queen,
+            "K" | "k" => Rank::King,
+            "A" | "a" => Rank::Ace,
+            "2" => Rank::Two,
+            "Jk" | "jk" | "JK" => Rank::Joker,
+            _ => return Err(ScumError::BadNotation(s.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for Suit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Suit::Clubs => "c",
+            Suit::Diamonds => "d",
+            Suit::Hearts => "h",
+            Suit::Spades => "s",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Suit {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<Suit, ScumError> {
+        Ok(match s {
+            "c" => Suit::Clubs,
+            "d" => Suit::Diamonds,
+            "h" => Suit::Hearts,
+            "s" => Suit::Spades,
+            _ => return Err(ScumError::BadNotation(s.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.typed_rank(), self.suit())
+    }
+}
+
+impl FromStr for Card {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<Card, ScumError> {
+        // The suit is the last character.
+        let last = s.char_indices().last().map_or(0, |(i, _)| i);
+        let (rank, suit) = (&s[..last], &s[last..]);
+        match (rank.parse::<Rank>(), suit.parse::<Suit>()) {
+            (Ok(rank), Ok(suit)) => Ok(Card::new(rank as u8, suit)),
+            _ => Err(ScumError::BadNotation(s.to_string())),
+        }
+    }
+}
+
+// Plays with ranks out of range print their raw numbers rather than panic.
+fn rank_string(rank: u8) -> String {
+    match Rank::from_u8(rank) {
+        Some(rank) => format!("{}", rank),
+        None => format!("?{}", rank),
+    }
+}
+
+impl fmt::Display for Play {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Set(count, rank) => write!(f, "{}x{}", count, rank_string(rank)),
+            Run(length, low) => write!(f, "{}-{}", rank_string(low),
+                                       rank_string(low + length - 1)),
+            Wild(count, rank, jokers) =>
+                write!(f, "{}x{}+{}Jk", count, rank_string(rank), jokers),
+        }
+    }
+}
+
+impl FromStr for Play {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<Play, ScumError> {
+        let bad = || ScumError::BadNotation(s.to_string());
+        let number = |n: &str| n.parse::<u8>().ok().ok_or(bad());
+        let rank = |r: &str| r.parse::<Rank>().map(|r| r as u8)
+            .map_err(|_| bad());
+        if let Some(x) = s.find('x') {
+            let count = try!(number(&s[..x]));
+            let set = &s[x + 1..];
+            if count == 0 {
+                return Err(bad());
+            }
+            match set.find('+') {
+                None => Ok(Set(count, try!(rank(set)))),
+                Some(plus) => {
+                    let jokers = &set[plus + 1..];
+                    if !jokers.ends_with("Jk") {
+                        return Err(bad());
+                    }
+                    let jokers = try!(number(&jokers[..jokers.len() - 2]));
+                    if jokers == 0 || jokers >= count {
+                        return Err(bad());
+                    }
+                    Ok(Wild(count, try!(rank(&set[..plus])), jokers))
+                },
+            }
+        } else if let Some(dash) = s.find('-') {
+            let low = try!(rank(&s[..dash]));
+            let high = try!(rank(&s[dash + 1..]));
+            if high < low {
+                return Err(bad());
+            }
+            Ok(Run(high - low + 1, low))
+        } else {
+            Err(bad())
+        }
+    }
+}
+
+pub fn move_string(muve: Move) -> String {
+    match muve {
+        Some(play) => format!("{}", play),
+        None => "pass".to_string(),
+    }
+}
+
+pub fn parse_move(s: &str) -> Result<Move, ScumError> {
+    match s.trim() {
+        "pass" => Ok(None),
+        s => s.parse().map(Some),
+    }
+}
+
+impl fmt::Display for Turn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {}", self.player, move_string(self.muve))
+    }
+}
+
+pub fn trick_string(trick: &[Turn]) -> String {
+    trick.iter().map(|t| format!("{}", t)).collect::<Vec<_>>().connect(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Card, Rank, ScumError, Suit, Turn, DECK, JOKER, NINE, QUEEN, SEVEN,
+         TEN, THREE};
+    use Play::{Run, Set, Wild};
+
+    #[test]
+    fn test_rank_notation() {
+        for rank in THREE..JOKER + 1 {
+            let rank = Rank::from_u8(rank).unwrap();
+            assert_eq!(Ok(rank), format!("{}", rank).parse());
+        }
+        assert_eq!("10", format!("{}", Rank::Ten));
+        assert_eq!("Jk", format!("{}", Rank::Joker));
+        assert_eq!(Ok(Rank::Queen), "q".parse());
+        assert!("1".parse::<Rank>().is_err());
+        assert_eq!(None, Rank::from_u8(0));
+    }
+
+    #[test]
+    fn test_card_notation() {
+        for card in DECK.iter() {
+            assert_eq!(Ok(*card), format!("{}", card).parse());
+        }
+        assert_eq!("10c", format!("{}", Card::new(TEN, Suit::Clubs)));
+        assert_eq!("Jkh", format!("{}", Card::new(JOKER, Suit::Hearts)));
+        for bad in ["", "c", "Q", "1s", "Qx"].iter() {
+            assert!(bad.parse::<Card>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_move_notation() {
+        let moves = [None, Some(Set(2, QUEEN)), Some(Set(1, JOKER)),
+                     Some(Run(3, TEN)), Some(Wild(3, NINE, 1))];
+        let strings = ["pass", "2xQ", "1xJk", "10-Q", "3x9+1Jk"];
+        for (muve, string) in moves.iter().zip(strings.iter()) {
+            assert_eq!(*string, move_string(*muve));
+            assert_eq!(Ok(*muve), parse_move(string));
+        }
+        assert_eq!(Ok(Some(Set(4, SEVEN))), parse_move(" 4x7\n"));
+        for bad in ["", "0x3", "2xB", "Q-10", "3x9+3Jk", "2x"].iter() {
+            assert_eq!(Err(ScumError::BadNotation(bad.to_string())),
+                       parse_move(bad));
+        }
+    }
+
+    #[test]
+    fn test_trick_notation() {
+        let trick = [Turn {player: 1, muve: Some(Set(2, QUEEN))},
+                     Turn {player: 2, muve: None},
+                     Turn {player: 0, muve: Some(Set(2, JOKER))}];
+        assert_eq!("#1 2xQ, #2 pass, #0 2xJk", trick_string(&trick));
+        assert_eq!("", trick_string(&[]));
+    }
+}
@@ -0,0 +1,261 @@
+// A record of one round, in the spirit of PGN: tags, a blank line, then the
+// moves with every trick on a line of its own.
+//
+//     [Player0 "Ann"]
+//     [Player1 "Bob"]
+//     [Player2 "Cy"]
+//     [Seed "42"]
+//     [Round "3"]
+//     [Deal "jcp 7c8c/5c6c/3c4c - 0 - 0,1,2 - - -"]
+//     [Result "0,1,2"]
+//
+//     1. 1x7 pass pass
+//     2. 1x8 pass pass
+//     3. 1x5 pass
+//     4. 1x6
+//
+// The seed is the one given to the program that played the game, and for a
+// match the round says which of its rounds this was, counting from 1. The
+// deal is the position after the card exchange, in the text form of the
+// fen module, so it carries the rules and the exchanges too. The result is
+// the finishing order and is only there for people: replaying the moves
+// works it out again. Moves are in the notation of the notation module, and
+// the trick numbers and unknown tags are skipped when reading.
+use std::fmt;
+use std::str::FromStr;
+
+use {parse_move, move_string, Move, ScumError, State};
+
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub players: Vec<String>,
+    pub seed: Option<u64>,
+    pub round: Option<usize>,
+    pub deal: State,
+    pub moves: Vec<Move>,
+}
+
+impl GameRecord {
+    pub fn new(players: Vec<String>, seed: Option<u64>, deal: &State)
+               -> GameRecord {
+        GameRecord {players: players, seed: seed, round: None,
+                    deal: deal.clone(), moves: vec![]}
+    }
+
+    pub fn push(&mut self, muve: Move) {
+        self.moves.push(muve);
+    }
+
+    // Every position of the game, from the deal to after the last move.
+    pub fn replay(&self) -> Result<Vec<State>, ScumError> {
+        let mut states = vec![self.deal.clone()];
+        let mut state = self.deal.clone();
+        for muve in self.moves.iter() {
+            try!(state.apply(*muve));
+            states.push(state.clone());
+        }
+        Ok(states)
+    }
+
+    // Reads all the records in `s`. A tag after some moves starts a new one.
+    pub fn parse_all(s: &str) -> Result<Vec<GameRecord>, ScumError> {
+        let mut records = vec![];
+        let mut lines: Vec<&str> = vec![];
+        let mut in_moves = false;
+        for line in s.lines() {
+            let is_tag = line.trim().starts_with("[");
+            if is_tag && in_moves {
+                records.push(try!(lines.connect("\n").parse()));
+                lines.clear();
+                in_moves = false;
+            }
+            in_moves |= !is_tag && !line.trim().is_empty();
+            lines.push(line);
+        }
+        if lines.iter().any(|l| !l.trim().is_empty()) {
+            records.push(try!(lines.connect("\n").parse()));
+        }
+        Ok(records)
+    }
+}
+
+// Splits `[Name "value"]` into its name and value.
+fn parse_tag(line: &str) -> Result<(&str, &str), ScumError> {
+    let bad = || ScumError::BadNotation(line.to_string());
+    let line = line.trim();
+    if !line.ends_with("]") {
+        return Err(bad());
+    }
+    let (open, close) = match (line.find('"'), line.rfind('"')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return Err(bad()),
+    };
+    Ok((line[1..open].trim(), &line[open + 1..close]))
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, name) in self.players.iter().enumerate() {
+            try!(writeln!(f, "[Player{} \"{}\"]", i, name));
+        }
+        if let Some(seed) = self.seed {
+            try!(writeln!(f, "[Seed \"{}\"]", seed));
+        }
+        if let Some(round) = self.round {
+            try!(writeln!(f, "[Round \"{}\"]", round));
+        }
+        try!(writeln!(f, "[Deal \"{}\"]", self.deal));
+
+        // Where the tricks start only shows by playing the moves.
+        let mut state = self.deal.clone();
+        let mut moves = String::new();
+        let mut tricks = 0;
+        for muve in self.moves.iter() {
+            if state.top_card().is_none() {
+                tricks += 1;
+                if tricks > 1 {
+                    moves.push('\n');
+                }
+                moves.push_str(&format!("{}.", tricks));
+            }
+            moves.push_str(&format!(" {}", move_string(*muve)));
+            // A bad move can't be replayed; it still gets written down.
+            let _ = state.apply(*muve);
+        }
+        if state.is_terminal() {
+            let order: Vec<String> = state.finishing_order().iter()
+                .map(|p| format!("{}", p)).collect();
+            try!(writeln!(f, "[Result \"{}\"]", order.connect(",")));
+        }
+        write!(f, "\n{}\n", moves)
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = ScumError;
+
+    fn from_str(s: &str) -> Result<GameRecord, ScumError> {
+        let mut players = vec![];
+        let mut seed = None;
+        let mut round = None;
+        let mut deal = None;
+        let mut moves = vec![];
+        for line in s.lines() {
+            if line.trim().starts_with("[") {
+                let (name, value) = try!(parse_tag(line));
+                let bad = || ScumError::BadNotation(line.to_string());
+                if name.starts_with("Player") {
+                    let seat: usize =
+                        try!(name[6..].parse().map_err(|_| bad()));
+                    while players.len() <= seat {
+                        players.push("?".to_string());
+                    }
+                    players[seat] = value.to_string();
+                } else if name == "Seed" {
+                    seed = Some(try!(value.parse().map_err(|_| bad())));
+                } else if name == "Round" {
+                    round = Some(try!(value.parse().map_err(|_| bad())));
+                } else if name == "Deal" {
+                    deal = Some(try!(value.parse::<State>()));
+                }
+                continue;
+            }
+            for word in line.split(' ').filter(|w| !w.is_empty()) {
+                if word.ends_with(".") {
+                    continue;
+                }
+                moves.push(try!(parse_move(word)));
+            }
+        }
+        let deal = try!(deal.ok_or(
+            ScumError::BadNotation("a record without a deal".to_string())));
+        Ok(GameRecord {players: players, seed: seed, round: round,
+                       deal: deal, moves: moves})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {RuleSet, ScumError, State};
+    use {THREE, FOUR, FIVE, SIX, SEVEN, EIGHT};
+    use Play::Set;
+    use tests::{deal, random_game};
+
+    use rand::weak_rng;
+    use std::default::Default;
+
+    fn names() -> Vec<String> {
+        vec!["Ann".to_string(), "Bob".to_string(), "Cy".to_string()]
+    }
+
+    #[test]
+    fn test_record_text() {
+        let start = State::new(3, deal(&[
+            THREE, FOUR, FIVE, SIX, SEVEN, EIGHT]), RuleSet::default());
+        let mut record = GameRecord::new(names(), Some(42), &start);
+        record.round = Some(3);
+        for m in [Some(Set(1, SEVEN)), None, None, Some(Set(1, EIGHT)), None,
+                  None, Some(Set(1, FIVE)), None, Some(Set(1, SIX))].iter() {
+            record.push(*m);
+        }
+        let text = "[Player0 \"Ann\"]\n[Player1 \"Bob\"]\n[Player2 \"Cy\"]\n\
+                    [Seed \"42\"]\n\
+                    [Round \"3\"]\n\
+                    [Deal \"jcp 7c8c/5c6c/3c4c - 0 - 0,1,2 - - -\"]\n\
+                    [Result \"0,1,2\"]\n\
+                    \n\
+                    1. 1x7 pass pass\n\
+                    2. 1x8 pass pass\n\
+                    3. 1x5 pass\n\
+                    4. 1x6\n";
+        assert_eq!(text, record.to_string());
+
+        let parsed: GameRecord = text.parse().unwrap();
+        assert_eq!(names(), parsed.players);
+        assert_eq!(Some(42), parsed.seed);
+        assert_eq!(Some(3), parsed.round);
+        assert_eq!(record.moves, parsed.moves);
+        let states = parsed.replay().unwrap();
+        assert_eq!(record.moves.len() + 1, states.len());
+        assert_eq!(&[0, 1, 2], states.last().unwrap().finishing_order());
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut record = None;
+        let mut states = vec![];
+        let last = random_game(RuleSet::default(), 4, &mut weak_rng(),
+                               |state, muve, _| {
+            if record.is_none() {
+                record = Some(GameRecord::new(vec![], None, state));
+            }
+            record.as_mut().unwrap().push(muve);
+            states.push(state.clone());
+        });
+        states.push(last);
+        let record = record.unwrap();
+
+        let text = format!("{}{}", record, record);
+        let parsed = GameRecord::parse_all(&text).unwrap();
+        assert_eq!(2, parsed.len());
+        let replayed = parsed[1].replay().unwrap();
+        assert_eq!(states.len(), replayed.len());
+        for (a, b) in states.iter().zip(replayed.iter()) {
+            assert_eq!(format!("{:?}", a), format!("{:?}", b));
+        }
+
+        // Moves after the end are caught.
+        let mut bad = parsed[0].clone();
+        bad.moves.push(record.moves[0]);
+        assert!(bad.replay().is_err());
+    }
+
+    #[test]
+    fn test_bad_record() {
+        assert!("1. 1x7".parse::<GameRecord>().is_err());
+        assert_eq!(Err(ScumError::BadNotation("1xZ".to_string())),
+                   "[Deal \"jcp 7c8c/5c6c/3c4c - 0 - 0,1,2 - - -\"]\n\n1. 1xZ"
+                       .parse::<GameRecord>().map(|r| r.moves));
+    }
+}
@@ -0,0 +1,136 @@
+use std::cmp::Ordering::Equal;
+use std::fmt;
+
+// When a match is over: once somebody reaches a score, or after a number of
+// rounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchEnd {
+    Points(i32),
+    Rounds(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Scoring {
+    points: Vec<i32>,
+    end: MatchEnd,
+}
+
+impl Scoring {
+    // `points[i]` is awarded for finishing `i`th, President first.
+    pub fn new(points: Vec<i32>, end: MatchEnd) -> Scoring {
+        Scoring {points: points, end: end}
+    }
+
+    // The President scores one point per player they beat, the Scum nothing.
+    pub fn linear(num_players: usize, end: MatchEnd) -> Scoring {
+        Scoring::new((0..num_players as i32).rev().collect(), end)
+    }
+
+    pub fn points(&self, position: usize) -> i32 {
+        *self.points.get(position).unwrap_or(&0)
+    }
+
+    pub fn end(&self) -> MatchEnd { self.end }
+
+    pub fn is_over(&self, standings: &Standings) -> bool {
+        match self.end {
+            MatchEnd::Points(target) =>
+                standings.totals.iter().any(|t| *t >= target),
+            MatchEnd::Rounds(rounds) => standings.rounds >= rounds,
+        }
+    }
+}
+
+// Running totals of a match.
+#[derive(Clone, Debug)]
+pub struct Standings {
+    totals: Vec<i32>,
+    rounds: usize,
+    last_round: Vec<u8>,
+}
+
+impl Standings {
+    pub fn new(num_players: usize) -> Standings {
+        Standings {totals: vec![0; num_players], rounds: 0, last_round: vec![]}
+    }
+
+    pub fn add_round(&mut self, scoring: &Scoring, finishing_order: &[u8]) {
+        assert_eq!(self.totals.len(), finishing_order.len());
+        for (position, player) in finishing_order.iter().enumerate() {
+            self.totals[*player as usize] += scoring.points(position);
+        }
+        self.rounds += 1;
+        self.last_round = finishing_order.to_vec();
+    }
+
+    pub fn total(&self, player: u8) -> i32 { self.totals[player as usize] }
+    pub fn rounds(&self) -> usize { self.rounds }
+
+    // Players from first to last. Ties go to whoever did better last round.
+    pub fn ranking(&self) -> Vec<u8> {
+        let mut players: Vec<u8> = (0..self.totals.len() as u8).collect();
+        players.sort_by(|a, b| {
+            match self.total(*b).cmp(&self.total(*a)) {
+                Equal => self.last_position(*a).cmp(&self.last_position(*b)),
+                ordering => ordering,
+            }
+        });
+        players
+    }
+
+    pub fn leader(&self) -> u8 { self.ranking()[0] }
+
+    fn last_position(&self, player: u8) -> usize {
+        self.last_round.iter().position(|p| *p == player).unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Standings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "After {} rounds:", self.rounds));
+        try!(writeln!(f, "{:>4}  {:>6}  {:>6}  {:>4}",
+            "Rank", "Player", "Points", "Last"));
+        for (rank, player) in self.ranking().iter().enumerate() {
+            let last = match self.last_round.iter().position(|p| p == player) {
+                Some(position) => format!("{}", position + 1),
+                None => "-".to_string(),
+            };
+            try!(writeln!(f, "{:>4}  {:>6}  {:>6}  {:>4}",
+                rank + 1, format!("#{}", player), self.total(*player), last));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standings() {
+        let scoring = Scoring::linear(3, MatchEnd::Points(3));
+        let mut standings = Standings::new(3);
+        standings.add_round(&scoring, &[2, 0, 1]);
+        assert_eq!(vec![2, 0, 1], standings.ranking());
+        assert!(!scoring.is_over(&standings));
+        standings.add_round(&scoring, &[0, 2, 1]);
+        assert_eq!(3, standings.total(0));
+        assert_eq!(3, standings.total(2));
+        assert_eq!(0, standings.total(1));
+        // Tied on points, player 0 finished ahead last round.
+        assert_eq!(vec![0, 2, 1], standings.ranking());
+        assert!(scoring.is_over(&standings));
+    }
+
+    #[test]
+    fn test_fixed_rounds() {
+        let scoring = Scoring::new(vec![5, 1], MatchEnd::Rounds(2));
+        let mut standings = Standings::new(3);
+        standings.add_round(&scoring, &[1, 2, 0]);
+        assert!(!scoring.is_over(&standings));
+        standings.add_round(&scoring, &[1, 0, 2]);
+        assert_eq!(10, standings.total(1));
+        assert_eq!(1, standings.total(0));
+        assert!(scoring.is_over(&standings));
+    }
+}
@@ -0,0 +1,69 @@
+// Reproducible randomness. A game has one seed: it shuffles the deck, and
+// every seat gets a stream of its own worked out from it, so that replaying
+// a seed deals the same cards and the AIs make the same decisions however
+// many random numbers the others use up.
+use rand::{SeedableRng, XorShiftRng};
+
+use zobrist::mix;
+
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    let a = mix(seed);
+    let b = mix(a);
+    // XorShift gets stuck on all zeroes.
+    XorShiftRng::from_seed([a as u32 | 1, (a >> 32) as u32,
+                            b as u32, (b >> 32) as u32])
+}
+
+// The seed of the AI in `seat`.
+pub fn player_seed(seed: u64, seat: u8) -> u64 {
+    mix(seed ^ mix(1 << 32 | seat as u64))
+}
+
+// The seed of round `round` of a match.
+pub fn round_seed(seed: u64, round: usize) -> u64 {
+    mix(seed ^ mix(2 << 32 | round as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {CheatingUCT, FairUCT, Move, Player, RuleSet, State, DECK};
+
+    use rand::Rng;
+    use std::default::Default;
+
+    fn play<P, F>(seed: u64, new_player: F) -> Vec<Move>
+        where P: Player, F: Fn() -> P {
+        let mut rng = seeded_rng(seed);
+        let mut deck = DECK.to_vec();
+        rng.shuffle(&mut deck[..]);
+        let mut state = State::new(3, deck, RuleSet::default());
+        let mut players: Vec<P> = (0..3).map(|seat| {
+            let mut player = new_player();
+            player.seed(player_seed(seed, seat));
+            player
+        }).collect();
+        let mut moves = vec![];
+        while !state.is_terminal() {
+            let seat = state.current_player() as usize;
+            let muve = players[seat].choose_move(state.clone());
+            state.apply(muve).unwrap();
+            moves.push(muve);
+        }
+        moves
+    }
+
+    #[test]
+    fn test_seeds() {
+        assert_eq!(seeded_rng(7).gen::<u64>(), seeded_rng(7).gen::<u64>());
+        assert!(seeded_rng(7).gen::<u64>() != seeded_rng(8).gen::<u64>());
+        assert!(player_seed(7, 0) != player_seed(7, 1));
+        assert!(round_seed(7, 0) != player_seed(7, 0));
+        assert_eq!(play(42, || CheatingUCT::new(50)),
+                   play(42, || CheatingUCT::new(50)));
+        // FairUCT adds up the visits of several searches, and ties between
+        // moves have to go the same way too.
+        assert_eq!(play(42, || FairUCT::new(5, 10)),
+                   play(42, || FairUCT::new(5, 10)));
+    }
+}
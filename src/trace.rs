@@ -0,0 +1,156 @@
+// Reads the logs the old ai_test printed, like comeback_win.trace.txt, into
+// game records. They start with a Debug dump of the deal
+//
+//     State { hands: [[1, 2, 3, 3, ...], ...], top_card: None, ... }
+//
+// followed by a line per move, with the player, the move as a (count, rank)
+// tuple and the state before it:
+//
+//     0 => Some((1, 1))   [State { ... }]
+//     2 => None           [State { ... }]
+//
+// Everything else, like the cargo output and the DEBUG tree dumps, is noise.
+// The hands only have ranks, so the suits are made up: every rank's cards
+// get the suits in DECK order. The games were played with the default rules,
+// except that they ended as soon as somebody went out.
+use std::default::Default;
+
+use {Card, CompactState, GameRecord, Hand, Move, RuleSet, ScumError, State,
+     DECK, MAX_PLAYERS};
+use Play::Set;
+
+fn bad(line: &str) -> ScumError {
+    ScumError::BadNotation(line.to_string())
+}
+
+// The hands of a `State { hands: [[...], ...], ... }` dump.
+fn parse_hands(line: &str) -> Result<Vec<Vec<u8>>, ScumError> {
+    let start = try!(line.find("hands: [[").ok_or(bad(line))) + 9;
+    let end = start + try!(line[start..].find("]]").ok_or(bad(line)));
+    line[start..end].split("], [").map(|hand| {
+        hand.split(", ").filter(|r| !r.is_empty())
+            .map(|r| r.parse().map_err(|_| bad(line))).collect()
+    }).collect()
+}
+
+// `0 => Some((1, 1))   [State ...]`, or None if the line isn't a move.
+fn parse_move_line(line: &str) -> Option<Result<(u8, Move), ScumError>> {
+    let arrow = match line.find(" => ") {
+        Some(arrow) if line[..arrow].parse::<u8>().is_ok() => arrow,
+        _ => return None,
+    };
+    let player = line[..arrow].parse().unwrap();
+    let rest = &line[arrow + 4..];
+    if rest.starts_with("None") {
+        return Some(Ok((player, None)));
+    }
+    if !rest.starts_with("Some((") {
+        return Some(Err(bad(line)));
+    }
+    let mut numbers = rest[6..].split(|c| c == ',' || c == ')')
+        .map(|n| n.trim().parse::<u8>().ok());
+    Some(match (numbers.next(), numbers.next()) {
+        (Some(Some(count)), Some(Some(rank))) =>
+            Ok((player, Some(Set(count, rank)))),
+        _ => Err(bad(line)),
+    })
+}
+
+// The deal, with suits handed out in DECK order.
+fn deal(hands: &[Vec<u8>], line: &str) -> Result<State, ScumError> {
+    if hands.len() > MAX_PLAYERS || hands.iter().any(|h| h.is_empty()) {
+        return Err(bad(line));
+    }
+    let mut deck = DECK.to_vec();
+    let mut dealt: Vec<Hand> = vec![];
+    for hand in hands.iter() {
+        let mut cards = vec![];
+        for &rank in hand.iter() {
+            let i = try!(deck.iter().position(|c| c.rank() == rank)
+                         .ok_or(bad(line)));
+            cards.push(deck.remove(i));
+        }
+        cards.sort();
+        dealt.push(cards);
+    }
+    let mut cards: Vec<Card> =
+        dealt.iter().flat_map(|h| h.iter()).cloned().collect();
+    cards.sort();
+    let rules = RuleSet {end_on_first_finisher: true, .. RuleSet::default()};
+    let core = CompactState::new(rules, &dealt, &[]);
+    Ok(State {core: core, hands: dealt, cards: cards, discard: vec![],
+              exchanges: vec![], history: vec![]})
+}
+
+// Turns a trace into a record, checking that every move was legal and made
+// by the player whose turn it was.
+pub fn import_trace(log: &str) -> Result<GameRecord, ScumError> {
+    let mut state: Option<State> = None;
+    let mut record = None;
+    for line in log.lines() {
+        let (player, muve) = match parse_move_line(line) {
+            Some(parsed) => try!(parsed),
+            None => {
+                if state.is_none() && line.starts_with("State {") {
+                    let start = try!(deal(&try!(parse_hands(line)), line));
+                    let names = vec!["ai_test".to_string();
+                                     start.num_players()];
+                    record = Some(GameRecord::new(names, None, &start));
+                    state = Some(start);
+                }
+                continue;
+            },
+        };
+        let state = try!(state.as_mut().ok_or(bad(line)));
+        if player != state.current_player() || !state.moves().contains(&muve) {
+            return Err(ScumError::IllegalMove(muve));
+        }
+        try!(state.apply(muve));
+        record.as_mut().unwrap().push(muve);
+    }
+    record.ok_or(ScumError::BadNotation("a trace without a deal".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {ScumError, THREE, FIVE, SIX};
+    use Play::Set;
+
+    #[test]
+    fn test_comeback_win() {
+        let record = import_trace(include_str!("../comeback_win.trace.txt"))
+            .unwrap();
+        assert_eq!(68, record.moves.len());
+        assert_eq!(Some(Set(1, THREE)), record.moves[0]);
+        let states = record.replay().unwrap();
+        let end = states.last().unwrap();
+        assert!(end.is_terminal());
+        assert_eq!(0, end.winner());
+    }
+
+    #[test]
+    fn test_bad_trace() {
+        let deal = "State { hands: [[1, 2], [3, 4]], top_card: None }\n";
+        let record = import_trace(&format!("{}DEBUG:scum: noise\n\
+                                            0 => Some((1, 1))   [State]\n\
+                                            1 => Some((1, 3))   [State]\n",
+                                           deal)).unwrap();
+        assert_eq!(vec![Some(Set(1, THREE)), Some(Set(1, FIVE))],
+                   record.moves);
+
+        // Out of turn.
+        assert_eq!(Err(ScumError::IllegalMove(Some(Set(1, FIVE)))),
+                   import_trace(&format!("{}1 => Some((1, 3))", deal))
+                       .map(|r| r.moves));
+        // Not in hand.
+        assert_eq!(Err(ScumError::IllegalMove(Some(Set(1, SIX)))),
+                   import_trace(&format!("{}0 => Some((1, 4))", deal))
+                       .map(|r| r.moves));
+        assert!(import_trace("0 => None").is_err());
+        // Five THREEs are more than the deck has.
+        let deal = "State { hands: [[1, 1, 1], [1, 1]], top_card: None }";
+        assert_eq!(Err(ScumError::BadNotation(deal.to_string())),
+                   import_trace(deal).map(|r| r.moves));
+    }
+}
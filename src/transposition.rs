@@ -0,0 +1,217 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::num::Float;
+
+use {rollout, CompactState, Move, UCTK};
+
+// UCT over positions rather than move sequences. Different orders of plays
+// and passes often reach the same position, and a table from Zobrist hashes
+// to positions lets them share their statistics, turning the tree into a DAG.
+pub struct TranspositionSearch {
+    positions: Vec<Position>,
+    table: HashMap<u64, usize>,
+    root: usize,
+    // Where the playouts are played.
+    scratch: CompactState,
+}
+
+struct Position {
+    // Kept to tell the position from another that hashes the same.
+    state: CompactState,
+    edges: Vec<Edge>,
+    untried_moves: Vec<Move>,
+    plays: f64,
+    // Playouts through here won by each player. Which player's wins count
+    // depends on who is choosing, so they're all kept.
+    wins: Vec<f64>,
+}
+
+// A move from one position to another. Its own visit count keeps the
+// exploration term honest when the child is also reached from elsewhere.
+struct Edge {
+    muve: Move,
+    child: usize,
+    plays: f64,
+}
+
+impl TranspositionSearch {
+    pub fn new<R: Rng>(state: &CompactState, rng: &mut R)
+                       -> TranspositionSearch {
+        let mut search = TranspositionSearch {
+            positions: vec![], table: HashMap::new(), root: 0,
+            scratch: *state};
+        search.root = search.position(state, rng).0;
+        search
+    }
+
+    // The number of distinct positions seen.
+    pub fn len(&self) -> usize { self.positions.len() }
+    // The number of moves between them. A plain tree has one less than it
+    // has positions.
+    pub fn edges(&self) -> usize {
+        self.positions.iter().fold(0, |n, p| n + p.edges.len())
+    }
+
+    // One iteration from the root. `state` has to be the position the search
+    // started from, and is the same again afterwards.
+    pub fn iterate<R: Rng>(&mut self, state: &mut CompactState, rng: &mut R) {
+        let root = self.root;
+        self.visit(root, state, rng);
+    }
+
+    // The move tried most often from the root.
+    pub fn best_move(&self) -> Move {
+        self.positions[self.root].edges.iter()
+            .max_by(|e| e.plays as usize)
+            .expect("no iterations were run").muve
+    }
+
+    // The position of `state`, and whether it is new.
+    fn position<R: Rng>(&mut self, state: &CompactState, rng: &mut R)
+                        -> (usize, bool) {
+        let hash = state.zobrist();
+        match self.table.get(&hash) {
+            Some(&id) if self.positions[id].state.same_position(state) =>
+                return (id, false),
+            _ => {},
+        }
+        let mut moves = state.moves();
+        rng.shuffle(&mut moves);
+        self.positions.push(Position {
+            state: *state,
+            edges: vec![],
+            untried_moves: moves,
+            plays: 0.,
+            wins: vec![0.; state.num_players()],
+        });
+        let id = self.positions.len() - 1;
+        // When two positions hash the same, the first keeps the table entry
+        // and the other goes without sharing.
+        if !self.table.contains_key(&hash) {
+            self.table.insert(hash, id);
+        }
+        (id, true)
+    }
+
+    fn visit<R: Rng>(&mut self, id: usize, state: &mut CompactState,
+                     rng: &mut R) -> u8 {
+        let untried = self.positions[id].untried_moves.pop();
+        let winner = if state.is_terminal() {
+            state.winner()
+        } else if let Some(muve) = untried {
+            let undo = state.apply(muve)
+                .ok().expect("generated an illegal move");
+            let (child, new) = self.position(state, rng);
+            self.positions[id].edges.push(
+                Edge {muve: muve, child: child, plays: 1.});
+            let winner = if new {
+                let winner = rollout(state, &mut self.scratch, rng);
+                self.update(child, winner);
+                winner
+            } else {
+                // Somebody got here first: carry on from what they found.
+                self.visit(child, state, rng)
+            };
+            state.undo(undo);
+            winner
+        } else {
+            let e = self.select(id, state.current_player());
+            let (muve, child) = {
+                let edge = &self.positions[id].edges[e];
+                (edge.muve, edge.child)
+            };
+            let undo = state.apply(muve)
+                .ok().expect("generated an illegal move");
+            let winner = self.visit(child, state, rng);
+            state.undo(undo);
+            self.positions[id].edges[e].plays += 1.;
+            winner
+        };
+        self.update(id, winner);
+        winner
+    }
+
+    fn select(&self, id: usize, player: u8) -> usize {
+        let position = &self.positions[id];
+        let log_plays = position.plays.ln();
+        (0..position.edges.len()).max_by(|e| {
+            let edge = &position.edges[*e];
+            let child = &self.positions[edge.child];
+            ((child.wins[player as usize] / child.plays
+              + UCTK * (log_plays / edge.plays).sqrt()) * 1000000.) as i64
+        }).unwrap()
+    }
+
+    fn update(&mut self, id: usize, winner: u8) {
+        let position = &mut self.positions[id];
+        position.plays += 1.;
+        position.wins[winner as usize] += 1.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Move, RuleSet, State};
+    use {THREE, FOUR, FIVE, SIX, SEVEN, EIGHT, NINE, TEN};
+    use Play::Set;
+    use tests::deal;
+
+    use rand::weak_rng;
+    use std::default::Default;
+
+    #[allow(non_snake_case)]
+    fn M(count: u8, card: u8) -> Move { Some(Set(count, card)) }
+
+    // Player 0 holds THREE, FOUR, SEVEN and EIGHT, player 1 FIVE, SIX, NINE
+    // and TEN.
+    fn two_tricks() -> State {
+        let cards = deal(&[FIVE, SIX, NINE, TEN, THREE, FOUR, SEVEN, EIGHT]);
+        State::new(2, cards, RuleSet::default())
+    }
+
+    #[test]
+    fn test_transposition() {
+        // Playing FIVE then SIX or SIX then FIVE over two tricks leaves the
+        // same position.
+        let mut a = two_tricks();
+        let mut b = two_tricks();
+        for m in [M(1, THREE), M(1, FIVE), None, M(1, SIX), M(1, SEVEN), None]
+                .iter() {
+            a.apply(*m).unwrap();
+        }
+        for m in [M(1, THREE), M(1, SIX), None, M(1, FIVE), M(1, SEVEN), None]
+                .iter() {
+            b.apply(*m).unwrap();
+        }
+        assert_eq!(a.zobrist(), b.zobrist());
+    }
+
+    #[test]
+    fn test_collision() {
+        let mut rng = weak_rng();
+        let mut state = two_tricks().compact();
+        let mut search = TranspositionSearch::new(&state, &mut rng);
+        state.apply(M(1, THREE)).unwrap();
+        // Make the position after the THREE hash like the root.
+        let root = search.root;
+        search.table.insert(state.zobrist(), root);
+        let (id, new) = search.position(&state, &mut rng);
+        assert!(new);
+        assert!(id != root);
+    }
+
+    #[test]
+    fn test_search_shares_positions() {
+        let mut rng = weak_rng();
+        let mut state = two_tricks().compact();
+        let mut search = TranspositionSearch::new(&state, &mut rng);
+        for _ in 0..2000 {
+            search.iterate(&mut state, &mut rng);
+        }
+        assert_eq!(format!("{:?}", two_tricks().compact()),
+                   format!("{:?}", state));
+        assert!(search.edges() >= search.len());
+        assert!(state.moves().contains(&search.best_move()));
+    }
+}
@@ -0,0 +1,115 @@
+// Zobrist keys: a pseudo-random number for every feature a position can have,
+// e.g. "player 2 holds three FIVEs". A position hashes to the xor of the keys
+// of its features, so moving cards only xors a few keys out and in again.
+// The keys are computed rather than looked up, so they are the same in every
+// run and need no setup.
+use Move;
+use Play::{Run, Set, Wild};
+
+const HAND: u64 = 1;
+const DISCARD: u64 = 2;
+const TOP_CARD: u64 = 3;
+const TOP_PLAYER: u64 = 4;
+const PASSES: u64 = 5;
+const REVOLUTION: u64 = 6;
+const QUEUE: u64 = 7;
+const FINISHED: u64 = 8;
+const HAND_SIZE: u64 = 9;
+const VIEWER: u64 = 10;
+const GIFT: u64 = 11;
+
+fn key(kind: u64, a: u64, b: u64) -> u64 {
+    mix(kind << 56 | a << 32 | b)
+}
+
+// The splitmix64 finaliser.
+pub fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// Having none of something is not a feature, so empty hands and piles need
+// no keys.
+pub fn hand(player: u8, rank: u8, count: u8) -> u64 {
+    if count == 0 { 0 } else {
+        key(HAND, player as u64, (rank as u64) << 8 | count as u64)
+    }
+}
+
+pub fn discard(rank: u8, count: u8) -> u64 {
+    if count == 0 { 0 } else {
+        key(DISCARD, 0, (rank as u64) << 8 | count as u64)
+    }
+}
+
+pub fn top_card(top: Move) -> u64 {
+    let code = match top {
+        None => return 0,
+        Some(Set(count, rank)) => 1 << 24 | (count as u64) << 8 | rank as u64,
+        Some(Run(length, low)) => 2 << 24 | (length as u64) << 8 | low as u64,
+        Some(Wild(count, rank, jokers)) => 3 << 24 | (jokers as u64) << 16
+            | (count as u64) << 8 | rank as u64,
+    };
+    key(TOP_CARD, 0, code)
+}
+
+pub fn top_player(player: Option<u8>) -> u64 {
+    player.map_or(0, |p| key(TOP_PLAYER, 0, p as u64))
+}
+
+pub fn passes(passes: u8) -> u64 {
+    if passes == 0 { 0 } else { key(PASSES, 0, passes as u64) }
+}
+
+pub fn revolution(revolution: bool) -> u64 {
+    if revolution { key(REVOLUTION, 0, 0) } else { 0 }
+}
+
+// The line of players to play in the current trick is keyed by who is at
+// its front and who follows whom, so that a player joining or leaving only
+// changes a couple of keys.
+pub fn queue_front(player: u8) -> u64 {
+    key(QUEUE, 0, player as u64)
+}
+
+pub fn queue_next(player: u8, next: u8) -> u64 {
+    key(QUEUE, 1 + player as u64, next as u64)
+}
+
+// The keys of a whole line.
+pub fn queue<I: Iterator<Item=u8>>(mut players: I) -> u64 {
+    let mut last = match players.next() {
+        Some(front) => front,
+        None => return 0,
+    };
+    let mut hash = queue_front(last);
+    for player in players {
+        hash ^= queue_next(last, player);
+        last = player;
+    }
+    hash
+}
+
+// `player` finished `position`th.
+pub fn finished(position: usize, player: u8) -> u64 {
+    key(FINISHED, position as u64, player as u64)
+}
+
+pub fn hand_size(player: u8, size: usize) -> u64 {
+    if size == 0 { 0 } else { key(HAND_SIZE, player as u64, size as u64) }
+}
+
+// Whose information set it is.
+pub fn viewer(player: u8) -> u64 {
+    key(VIEWER, 0, player as u64)
+}
+
+// `count` cards of `rank` went from `giver` to `receiver` before play.
+pub fn gift(giver: u8, receiver: u8, rank: u8, count: u8) -> u64 {
+    if count == 0 { 0 } else {
+        key(GIFT, (giver as u64) << 8 | receiver as u64,
+            (rank as u64) << 8 | count as u64)
+    }
+}